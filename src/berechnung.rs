@@ -1,18 +1,36 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
 use anyhow::{Context, Result};
-use chrono::{Datelike, Days};
-use num_traits::identities::Zero;
+use chrono::{Datelike, Days, Months};
+use num_traits::identities::{One, Zero};
 
 use crate::cacher::Cacher;
+use crate::dba::{self, Land};
 use crate::format;
 use crate::kursdaten::Kursabfrage;
-use crate::meldungen::Meldungen;
+use crate::meldungen::{FondMeldung, Meldungen};
 use crate::steuern::{
-    ausgliederung_berechnen, ausschüttung_berechnen, ausschüttungsgleiche_beträge_berechnen,
-    dividende_berechnen, einbuchung_berechnen, kauf_berechnen, meldung_berechnen,
-    spitzenverwertung_berechnen, split_berechnen, verkauf_berechnen,
+    aktiendividende_berechnen, anleihe_kauf_berechnen, anleihe_verkauf_berechnen,
+    ausbuchung_berechnen, ausgliederung_berechnen, ausschüttung_berechnen,
+    ausschüttungsgleiche_beträge_berechnen, ausübung_berechnen, bezugsrecht_ausübung_berechnen,
+    bezugsrecht_berechnen, bezugsrecht_verkauf_berechnen, depotausgang_berechnen,
+    depoteingang_berechnen, derivat_steuer, dividende_berechnen,
+    einbuchung_ausgliederung_berechnen, einbuchung_berechnen, einlagenrückzahlung_berechnen,
+    fondsverschmelzung_berechnen, glattstellung_berechnen, gratisaktien_berechnen,
+    kauf_altbestand_berechnen, kauf_berechnen, krypto_steuer, kupon_berechnen, meldung_berechnen,
+    runde, rückerstattung_berechnen, schenkung_berechnen, spitzenverwertung_berechnen,
+    split_berechnen, staking_berechnen, stillhalter_berechnen, tilgung_berechnen,
+    umtausch_berechnen, umtauscheingang_berechnen, unentgeltlicher_erwerb_berechnen,
+    verfall_berechnen, verkauf_berechnen, verkauf_schätzung_berechnen, zugang_berechnen, Ergebnis,
 };
 use crate::waehrungen::{Kurs, Währungen};
-use crate::{Bestand, Datum, Jahr, Transaktion, TransaktionsTyp, Wertpapier, WertpapierTyp, Zahl};
+use crate::{
+    Altbestand, Bestand, Datum, Fremdwährung, Jahr, Kursquelle, String, Transaktion,
+    TransaktionsTyp, Verkehrswert, Wertpapier, WertpapierTyp, Zahl,
+};
+
+/// Depot und ISIN, für die ein eigener Bestand geführt wird.
+type Schlüssel = (Option<String>, String);
 
 #[derive(Debug)]
 pub struct Rechner {
@@ -20,6 +38,28 @@ pub struct Rechner {
     meldungen: Meldungen,
     kursabfrage: Kursabfrage,
     währungen: Währungen,
+    überträge: HashMap<Schlüssel, Vec<Übertrag>>,
+}
+
+/// Bestand, der aus einem Depot ausgebucht wurde, und im Ziel noch eingebucht werden muss.
+#[derive(Debug)]
+struct Übertrag {
+    datum: Datum,
+    quelle: Quelle,
+    bestand: Bestand,
+}
+
+#[derive(Debug, PartialEq)]
+enum Quelle {
+    /// Übertrag aus einem anderen Depot
+    Depot(Option<String>),
+    /// Umtausch aus einem anderen Wertpapier
+    Wertpapier(String),
+    /// Ausgliederung aus einem anderen Wertpapier
+    Ausgliederung(String),
+    /// Kauf oder Verkauf des Basiswertes bei Ausübung einer Option, mit dem Basispreis als
+    /// Preis des Bestandes
+    Ausübung { derivat: String, kauf: bool },
 }
 impl Rechner {
     pub async fn new() -> Result<Self> {
@@ -33,9 +73,372 @@ impl Rechner {
             meldungen,
             kursabfrage,
             währungen,
+            überträge: Default::default(),
+        })
+    }
+
+    /// Stellt sicher, dass alle Depotausgänge, Umtausche und Ausgliederungen auch wieder
+    /// eingebucht wurden.
+    pub fn offene_überträge_prüfen(&self) -> Result<()> {
+        for ((depot, isin), überträge) in &self.überträge {
+            let Some(übertrag) = überträge.first() else {
+                continue;
+            };
+            match &übertrag.quelle {
+                Quelle::Depot(_) => anyhow::bail!(
+                    "Depotausgang von `{isin}` am {} nach `{}` wurde nicht eingebucht",
+                    übertrag.datum,
+                    depot.as_deref().unwrap_or_default(),
+                ),
+                Quelle::Wertpapier(quelle) => anyhow::bail!(
+                    "Umtausch von `{quelle}` am {} in `{isin}` wurde nicht eingebucht",
+                    übertrag.datum,
+                ),
+                Quelle::Ausgliederung(quelle) => anyhow::bail!(
+                    "Ausgliederung von `{isin}` aus `{quelle}` am {} wurde nicht eingebucht",
+                    übertrag.datum,
+                ),
+                Quelle::Ausübung { derivat, .. } => anyhow::bail!(
+                    "Ausübung von `{derivat}` am {} fehlt ein Basiswert `{isin}`",
+                    übertrag.datum,
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn depotausgang(
+        &mut self,
+        bestand: Bestand,
+        (depot, isin): &Schlüssel,
+        datum: Datum,
+        stück: Zahl,
+        ziel: String,
+    ) -> Result<Ergebnis> {
+        anyhow::ensure!(
+            stück <= bestand.stück,
+            "Depotausgang von {stück} Stück, bei nur {} Stück Bestand",
+            bestand.stück
+        );
+        let (ergebnis, bestand) = depotausgang_berechnen(bestand, stück, ziel.clone());
+        self.überträge
+            .entry((Some(ziel), isin.clone()))
+            .or_default()
+            .push(Übertrag {
+                datum,
+                quelle: Quelle::Depot(depot.clone()),
+                bestand,
+            });
+        Ok(ergebnis)
+    }
+
+    fn depoteingang(
+        &mut self,
+        bestand: Bestand,
+        schlüssel: &Schlüssel,
+        datum: Datum,
+        stück: Zahl,
+        quelle: String,
+    ) -> Result<Ergebnis> {
+        let übertrag = self
+            .übertrag_entnehmen(schlüssel, datum, |q| {
+                *q == Quelle::Depot(Some(quelle.clone()))
+            })
+            .with_context(|| format!("Depoteingang ohne passenden Depotausgang in `{quelle}`"))?;
+        anyhow::ensure!(
+            übertrag.bestand.stück == stück,
+            "Depoteingang von {stück} Stück, aber Depotausgang am {} von {} Stück",
+            übertrag.datum,
+            übertrag.bestand.stück
+        );
+
+        Ok(depoteingang_berechnen(bestand, übertrag.bestand, quelle))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn umtausch(
+        &mut self,
+        bestand: Bestand,
+        (depot, isin): &Schlüssel,
+        datum: Datum,
+        stück: Zahl,
+        ziel: String,
+        neue_stück: Zahl,
+        zuzahlung: Option<format::Betrag>,
+    ) -> Result<(Ergebnis, Option<Fremdwährung>)> {
+        anyhow::ensure!(
+            stück > Zahl::zero() && neue_stück > Zahl::zero(),
+            "Umtausch von {stück} Stück in {neue_stück} Stück, beide müssen größer Null sein"
+        );
+        anyhow::ensure!(
+            stück <= bestand.stück,
+            "Umtausch von {stück} Stück, bei nur {} Stück Bestand",
+            bestand.stück
+        );
+        let (zuzahlung, fremdwährung) = self
+            .in_euro(zuzahlung.unwrap_or_default(), datum, 2)
+            .await?;
+        let (ergebnis, bestand) =
+            umtausch_berechnen(bestand, stück, ziel.clone(), neue_stück, zuzahlung);
+        self.überträge
+            .entry((depot.clone(), ziel))
+            .or_default()
+            .push(Übertrag {
+                datum,
+                quelle: Quelle::Wertpapier(isin.clone()),
+                bestand,
+            });
+        Ok((ergebnis, fremdwährung))
+    }
+
+    fn umtauscheingang(
+        &mut self,
+        bestand: Bestand,
+        schlüssel: &Schlüssel,
+        datum: Datum,
+        stück: Zahl,
+        quelle: String,
+    ) -> Result<Ergebnis> {
+        let übertrag = self
+            .übertrag_entnehmen(schlüssel, datum, |q| {
+                *q == Quelle::Wertpapier(quelle.clone())
+            })
+            .with_context(|| format!("Umtauscheingang ohne passenden Umtausch von `{quelle}`"))?;
+        anyhow::ensure!(
+            übertrag.bestand.stück == stück,
+            "Umtauscheingang von {stück} Stück, aber Umtausch am {} in {} Stück",
+            übertrag.datum,
+            übertrag.bestand.stück
+        );
+
+        Ok(umtauscheingang_berechnen(bestand, übertrag.bestand, quelle))
+    }
+
+    fn übertrag_entnehmen(
+        &mut self,
+        schlüssel: &Schlüssel,
+        datum: Datum,
+        quelle: impl Fn(&Quelle) -> bool,
+    ) -> Option<Übertrag> {
+        let überträge = self.überträge.get_mut(schlüssel)?;
+        let idx = überträge
+            .iter()
+            .position(|ü| quelle(&ü.quelle) && ü.datum <= datum)?;
+        Some(überträge.remove(idx))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn ausgliederung(
+        &mut self,
+        bestand: Bestand,
+        (depot, isin): &Schlüssel,
+        datum: Datum,
+        faktor: Zahl,
+        andere_isin: String,
+        eigener_kurs: Option<format::Betrag>,
+        anderer_kurs: Option<format::Betrag>,
+    ) -> Result<Ergebnis> {
+        let eigener_kurs = self.verkehrswert(eigener_kurs, isin, datum).await?;
+        let anderer_kurs = self.verkehrswert(anderer_kurs, &andere_isin, datum).await?;
+        anyhow::ensure!(
+            eigener_kurs.wert >= Zahl::zero() && anderer_kurs.wert >= Zahl::zero(),
+            "Ausgliederung von `{andere_isin}` mit negativem Verkehrswert"
+        );
+        let gesamtwert = bestand.stück * (eigener_kurs.wert + faktor * anderer_kurs.wert);
+        anyhow::ensure!(
+            gesamtwert > Zahl::zero(),
+            "Ausgliederung von `{andere_isin}` ohne Bestand oder Verkehrswert, die Anschaffungskosten \
+             können nicht aufgeteilt werden"
+        );
+
+        let (ergebnis, abgespalten) = ausgliederung_berechnen(
+            bestand,
+            faktor,
+            andere_isin.clone(),
+            eigener_kurs,
+            anderer_kurs,
+        );
+        self.überträge
+            .entry((depot.clone(), andere_isin))
+            .or_default()
+            .push(Übertrag {
+                datum,
+                quelle: Quelle::Ausgliederung(isin.clone()),
+                bestand: abgespalten,
+            });
+        Ok(ergebnis)
+    }
+
+    /// Der angegebene Verkehrswert je Stück, oder ansonsten der Eröffnungskurs des Tages.
+    async fn verkehrswert(
+        &mut self,
+        angegeben: Option<format::Betrag>,
+        isin: &str,
+        datum: Datum,
+    ) -> Result<Verkehrswert> {
+        if let Some(angegeben) = angegeben {
+            let (wert, fremdwährung) = self.in_euro(angegeben, datum, 4).await?;
+            return Ok(Verkehrswert {
+                wert,
+                quelle: Kursquelle::Angegeben,
+                fremdwährung,
+            });
+        }
+
+        let kurs = self
+            .kursabfrage
+            .kurs_für_isin(isin, datum)
+            .await
+            .with_context(|| {
+                format!("Kein Kurs für `{isin}` am {datum}, bitte den Verkehrswert angeben")
+            })?;
+        let wert = self
+            .währungen
+            .kurs_in_euro(Kurs {
+                wert: kurs.open,
+                währung: kurs.währung,
+                datum,
+            })
+            .await?;
+        Ok(Verkehrswert {
+            wert,
+            quelle: Kursquelle::Abgefragt,
+            fremdwährung: None,
         })
     }
 
+    /// Bucht die bei einer Ausgliederung abgespaltenen Aktien mitsamt ihren Anschaffungskosten
+    /// ein. Ohne vorhergehende Ausgliederung wird der angegebene Verkehrswert, oder der Kurs zum
+    /// Zeitpunkt der Einbuchung angesetzt.
+    async fn einbuchung(
+        &mut self,
+        bestand: Bestand,
+        schlüssel: &Schlüssel,
+        datum: Datum,
+        stück: Zahl,
+        angegeben: Option<format::Betrag>,
+        symbol: Option<&str>,
+    ) -> Result<(Ergebnis, Option<Fremdwährung>)> {
+        let übertrag = self.übertrag_entnehmen(schlüssel, datum, |quelle| {
+            matches!(quelle, Quelle::Ausgliederung(_))
+        });
+        if let Some(übertrag) = übertrag {
+            let spitze = übertrag.bestand.stück - stück;
+            anyhow::ensure!(
+                spitze >= Zahl::zero() && spitze < Zahl::one(),
+                "Einbuchung von {stück} Stück, aber Ausgliederung am {} von {} Stück",
+                übertrag.datum,
+                übertrag.bestand.stück
+            );
+            let ergebnis = einbuchung_ausgliederung_berechnen(bestand, übertrag.bestand);
+            return Ok((ergebnis, None));
+        }
+
+        if let Some(angegeben) = angegeben {
+            let (preis, fremdwährung) = self.in_euro(angegeben, datum, 4).await?;
+            let ergebnis = einbuchung_berechnen(bestand, stück, preis, Kursquelle::Angegeben);
+            return Ok((ergebnis, fremdwährung));
+        }
+
+        let symbol = symbol.context("Aktie sollte ein Symbol haben")?;
+        let kurs = self.kursabfrage.kurs_abrufen(symbol, datum).await?;
+        let kurs = self
+            .währungen
+            .kurs_in_euro(Kurs {
+                wert: kurs.open,
+                währung: kurs.währung,
+                datum: kurs.datum,
+            })
+            .await?;
+        let ergebnis = einbuchung_berechnen(bestand, stück, kurs, Kursquelle::Abgefragt);
+        Ok((ergebnis, None))
+    }
+
+    /// Ergänzt die Käufe und Verkäufe aus der Ausübung von Optionen auf dieses Wertpapier.
+    fn andienungen_ergänzen(
+        &mut self,
+        schlüssel: &Schlüssel,
+        transaktionen: &mut Vec<format::Transaktion>,
+    ) {
+        let Some(überträge) = self.überträge.get_mut(schlüssel) else {
+            return;
+        };
+        let (andienungen, rest): (Vec<_>, Vec<_>) = std::mem::take(überträge)
+            .into_iter()
+            .partition(|ü| matches!(ü.quelle, Quelle::Ausübung { .. }));
+        *überträge = rest;
+
+        for andienung in andienungen {
+            let Quelle::Ausübung { kauf, .. } = andienung.quelle else {
+                continue;
+            };
+            let stück = format::Zahl(andienung.bestand.stück);
+            let preis = format::Betrag {
+                wert: andienung.bestand.preis,
+                währung: None,
+            };
+            transaktionen.push(if kauf {
+                format::Transaktion::Kauf(andienung.datum, stück, preis, None, None)
+            } else {
+                format::Transaktion::Verkauf(andienung.datum, stück, preis, None, None)
+            });
+        }
+    }
+
+    /// Ergänzt fehlende Einbuchungen für die in dieses Wertpapier ausgegliederten Aktien.
+    fn einbuchungen_ergänzen(
+        &self,
+        schlüssel: &Schlüssel,
+        transaktionen: &mut Vec<format::Transaktion>,
+    ) {
+        let Some(überträge) = self.überträge.get(schlüssel) else {
+            return;
+        };
+        let mut einbuchungen: Vec<_> = transaktionen
+            .iter()
+            .filter(|t| matches!(t, format::Transaktion::Einbuchung(..)))
+            .map(|t| t.datum())
+            .collect();
+        einbuchungen.sort();
+
+        for übertrag in überträge {
+            if !matches!(übertrag.quelle, Quelle::Ausgliederung(_)) {
+                continue;
+            }
+            if let Some(idx) = einbuchungen.iter().position(|d| *d >= übertrag.datum) {
+                einbuchungen.remove(idx);
+            } else {
+                transaktionen.push(format::Transaktion::Einbuchung(
+                    übertrag.datum,
+                    format::Zahl(übertrag.bestand.stück),
+                    None,
+                ));
+            }
+        }
+    }
+
+    /// Aktien, in die ausgegliedert wurde, für die es aber keine eigenen Daten gibt.
+    pub fn ausgegliederte_wertpapiere(&self) -> Vec<format::Wertpapier> {
+        self.überträge
+            .iter()
+            .filter(|(_, überträge)| {
+                überträge
+                    .iter()
+                    .any(|ü| matches!(ü.quelle, Quelle::Ausgliederung(_)))
+            })
+            .map(|((depot, isin), _)| format::Wertpapier {
+                typ: WertpapierTyp::Aktie,
+                name: isin.clone(),
+                isin: isin.clone(),
+                symbol: None,
+                depot: depot.clone(),
+                land: None,
+                quellensteuersatz: None,
+                transaktionen: vec![],
+            })
+            .collect()
+    }
+
     pub async fn wertpapier_auswerten(
         &mut self,
         wertpapier: format::Wertpapier,
@@ -43,6 +446,9 @@ impl Rechner {
         match wertpapier.typ {
             WertpapierTyp::Aktie => self.aktie_auswerten(wertpapier).await,
             WertpapierTyp::Etf => self.etf_auswerten(wertpapier).await,
+            WertpapierTyp::Anleihe => self.anleihe_auswerten(wertpapier).await,
+            WertpapierTyp::Krypto => self.krypto_auswerten(wertpapier).await,
+            WertpapierTyp::Derivat => self.derivat_auswerten(wertpapier).await,
             WertpapierTyp::Fond => {
                 let wertpapier = self.aktie_auswerten(wertpapier).await?;
                 self.fond_auswerten(wertpapier).await
@@ -56,10 +462,17 @@ impl Rechner {
             mut name,
             isin,
             mut symbol,
+            depot,
+            land,
+            quellensteuersatz,
             mut transaktionen,
-            ..
         } = wertpapier;
+        let land = Land::bestimmen(&isin, land.as_deref())?;
+        let quellensteuersatz = quellensteuersatz.map(|format::Zahl(satz)| satz);
 
+        let schlüssel = (depot.clone(), isin.clone());
+        self.einbuchungen_ergänzen(&schlüssel, &mut transaktionen);
+        self.andienungen_ergänzen(&schlüssel, &mut transaktionen);
         transaktionen.sort_by_key(|t| t.datum());
 
         let suche = symbol.as_deref().unwrap_or(isin.as_str());
@@ -73,74 +486,180 @@ impl Rechner {
 
         for transaktion in transaktionen {
             let datum = transaktion.datum();
+            let mut fremdwährung = None;
 
             let (bestand_, typ, steuer) = match transaktion {
-                format::Transaktion::Kauf(_, format::Zahl(stück), format::Zahl(preis), _) => {
-                    kauf_berechnen(bestand, stück, preis)
+                format::Transaktion::Kauf(.., Some(_))
+                | format::Transaktion::Verkauf(.., Some(_)) => {
+                    anyhow::bail!("Stückzinsen gibt es nur bei Anleihen");
+                }
+                format::Transaktion::Tilgung(..) | format::Transaktion::Kupon(..) => {
+                    anyhow::bail!("Tilgungen und Kupons gibt es nur bei Anleihen");
                 }
-                format::Transaktion::Verkauf(_, format::Zahl(stück), format::Zahl(preis), _) => {
-                    verkauf_berechnen(bestand, stück, preis)
+                format::Transaktion::Staking(..) => {
+                    anyhow::bail!("Staking gibt es nur bei Kryptowährungen");
+                }
+                format::Transaktion::Stillhalter(..)
+                | format::Transaktion::Glattstellung(..)
+                | format::Transaktion::Verfall(..)
+                | format::Transaktion::Ausübung(..) => {
+                    anyhow::bail!("Optionsgeschäfte gibt es nur bei Derivaten");
                 }
 
                 format::Transaktion::Split(_, format::Zahl(faktor)) => {
                     split_berechnen(bestand, faktor)
                 }
-                format::Transaktion::Ausgliederung(_, format::Zahl(faktor), andere_isin) => {
-                    let eigener_kurs = self.kursabfrage.kurs_für_isin(&isin, datum).await?;
-                    let eigener_kurs = self
-                        .währungen
-                        .kurs_in_euro(Kurs {
-                            wert: eigener_kurs.open,
-                            währung: eigener_kurs.währung,
-                            datum,
-                        })
-                        .await?;
-                    let anderer_kurs = self.kursabfrage.kurs_für_isin(&andere_isin, datum).await?;
-                    let anderer_kurs = self
-                        .währungen
-                        .kurs_in_euro(Kurs {
-                            wert: anderer_kurs.open,
-                            währung: anderer_kurs.währung,
-                            datum,
-                        })
+                format::Transaktion::Fondsverschmelzung(
+                    _,
+                    neue_isin,
+                    format::Zahl(faktor),
+                    neues_symbol,
+                ) => {
+                    anyhow::ensure!(
+                        typ == WertpapierTyp::Fond,
+                        "Fondsverschmelzung ist nur bei Fonds möglich"
+                    );
+                    let neues_symbol = self.fond_symbol(&neue_isin, neues_symbol).await?;
+                    fondsverschmelzung_berechnen(bestand, neue_isin, neues_symbol, faktor)
+                }
+                format::Transaktion::Gratisaktien(_, format::Zahl(stück)) => {
+                    anyhow::ensure!(
+                        !bestand.stück.is_zero(),
+                        "Gratisaktien ohne bestehenden Bestand"
+                    );
+                    gratisaktien_berechnen(bestand, stück)
+                }
+                format::Transaktion::Aktiendividende(
+                    _,
+                    format::Zahl(stück),
+                    preis,
+                    quellensteuer,
+                ) => {
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    let (quellensteuer, _) = self
+                        .in_euro(quellensteuer.unwrap_or_default(), datum, 2)
                         .await?;
-
-                    ausgliederung_berechnen(
+                    let satz = dba::quellensteuersatz(land, quellensteuersatz, datum.year());
+                    aktiendividende_berechnen(bestand, satz, stück, preis, quellensteuer)
+                }
+                format::Transaktion::Ausgliederung(..) if typ == WertpapierTyp::Fond => {
+                    anyhow::bail!("Fonds haben keine Ausgliederungen");
+                }
+                format::Transaktion::Ausgliederung(
+                    _,
+                    format::Zahl(faktor),
+                    andere_isin,
+                    eigener_kurs,
+                    anderer_kurs,
+                ) => {
+                    self.ausgliederung(
                         bestand,
+                        &schlüssel,
+                        datum,
                         faktor,
                         andere_isin,
                         eigener_kurs,
                         anderer_kurs,
                     )
+                    .await?
                 }
-                format::Transaktion::Einbuchung(_, format::Zahl(stück)) => {
-                    let symbol = symbol.as_deref().context("Aktie sollte ein Symbol haben")?;
-                    let kurs = self.kursabfrage.kurs_abrufen(symbol, datum).await?;
-                    let kurs = self
-                        .währungen
-                        .kurs_in_euro(Kurs {
-                            wert: kurs.open,
-                            währung: kurs.währung,
-                            datum: kurs.datum,
-                        })
+                format::Transaktion::Einbuchung(_, format::Zahl(stück), kurs) => {
+                    let (ergebnis, währung) = self
+                        .einbuchung(bestand, &schlüssel, datum, stück, kurs, symbol.as_deref())
                         .await?;
-                    einbuchung_berechnen(bestand, stück, kurs)
+                    fremdwährung = währung;
+                    ergebnis
+                }
+                format::Transaktion::Bezugsrecht(_, format::Zahl(anzahl)) => {
+                    bezugsrecht_berechnen(bestand, anzahl)
                 }
-                format::Transaktion::Spitzenverwertung(
+                format::Transaktion::Bezugsrechtverkauf(_, format::Zahl(anzahl), preis) => {
+                    bezugsrechte_prüfen(bestand, anzahl)?;
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    bezugsrecht_verkauf_berechnen(bestand, anzahl, preis)
+                }
+                format::Transaktion::Bezugsrechtausübung(
                     _,
+                    format::Zahl(anzahl),
                     format::Zahl(stück),
-                    format::Zahl(preis),
-                ) => spitzenverwertung_berechnen(bestand, stück, preis),
+                    preis,
+                ) => {
+                    bezugsrechte_prüfen(bestand, anzahl)?;
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    bezugsrecht_ausübung_berechnen(bestand, anzahl, stück, preis)
+                }
+                format::Transaktion::Spitzenverwertung(_, format::Zahl(stück), preis) => {
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    spitzenverwertung_berechnen(bestand, stück, preis)
+                }
+                format::Transaktion::Ausbuchung(_, preis) => {
+                    let (preis, währung) =
+                        self.in_euro(preis.unwrap_or_default(), datum, 4).await?;
+                    fremdwährung = währung;
+                    ausbuchung_berechnen(bestand, preis)
+                }
 
-                format::Transaktion::Dividende(
-                    _,
-                    format::Zahl(brutto),
-                    format::Zahl(auszahlung),
-                ) => dividende_berechnen(bestand, &isin, brutto, auszahlung),
+                format::Transaktion::Dividende(..) if typ == WertpapierTyp::Fond => {
+                    anyhow::bail!("Ausschüttungen von Fonds sind als `ausschüttung` anzugeben");
+                }
+                format::Transaktion::Dividende(_, brutto, auszahlung) => {
+                    anyhow::ensure!(
+                        brutto.währung == auszahlung.währung,
+                        "Brutto und Auszahlung der Dividende sind in der gleichen Währung anzugeben"
+                    );
+                    let (brutto, währung) = self.in_euro(brutto, datum, 2).await?;
+                    let (auszahlung, ausgezahlt) = self.in_euro(auszahlung, datum, 2).await?;
+                    fremdwährung = währung.map(|währung| Fremdwährung {
+                        auszahlung: ausgezahlt.map(|a| a.betrag),
+                        ..währung
+                    });
+                    let satz = dba::quellensteuersatz(land, quellensteuersatz, datum.year());
+                    dividende_berechnen(bestand, satz, brutto, auszahlung)
+                }
+                format::Transaktion::Rückerstattung(..) if typ == WertpapierTyp::Fond => {
+                    anyhow::bail!("Fonds haben keine Rückerstattungen von Quellensteuer");
+                }
+                format::Transaktion::Rückerstattung(_, betrag, jahr) => {
+                    anyhow::ensure!(
+                        jahr <= datum.year(),
+                        "Rückerstattung am {datum} für Dividenden aus dem Jahr {jahr}"
+                    );
+                    let (betrag, währung) = self.in_euro(betrag, datum, 2).await?;
+                    fremdwährung = währung;
+                    rückerstattung_berechnen(bestand, betrag, land, jahr)
+                }
+
+                format::Transaktion::Einlagenrückzahlung(_, betrag) => {
+                    anyhow::ensure!(
+                        !bestand.stück.is_zero(),
+                        "Einlagenrückzahlung ohne bestehenden Bestand"
+                    );
+                    let (betrag, währung) = self.in_euro(betrag, datum, 2).await?;
+                    fremdwährung = währung;
+                    einlagenrückzahlung_berechnen(bestand, betrag)
+                }
 
+                format::Transaktion::Ausschüttung(_, format::Zahl(auszahlung))
+                    if typ == WertpapierTyp::Fond =>
+                {
+                    ausschüttung_berechnen(bestand, auszahlung, None)
+                }
                 format::Transaktion::Ausschüttung(..) => {
                     anyhow::bail!("Aktien haben keine Ausschüttungen");
                 }
+
+                // Kauf, Verkauf, Umtausch und Überträge
+                transaktion => {
+                    let (ergebnis, währung) = self
+                        .bestand_ändern(typ, bestand, &schlüssel, transaktion)
+                        .await?;
+                    fremdwährung = währung;
+                    ergebnis
+                }
             };
             bestand = bestand_;
             let transaktion = Transaktion {
@@ -148,6 +667,7 @@ impl Rechner {
                 bestand,
                 typ,
                 steuer,
+                fremdwährung,
             };
             transaktion_anfügen(&mut jahre, transaktion);
         }
@@ -159,6 +679,7 @@ impl Rechner {
             name,
             isin,
             symbol,
+            depot,
             jahre,
         })
     }
@@ -166,20 +687,29 @@ impl Rechner {
     pub async fn etf_auswerten(&mut self, wertpapier: format::Wertpapier) -> Result<Wertpapier> {
         let format::Wertpapier {
             typ,
+            name,
             isin,
             symbol,
+            depot,
             mut transaktionen,
             ..
         } = wertpapier;
 
+        self.andienungen_ergänzen(&(depot.clone(), isin.clone()), &mut transaktionen);
         transaktionen.sort_by_key(|t| t.datum());
 
         let meldungsdaten = self.meldungen.fetch_meldungen(&isin).await?;
-        let name = meldungsdaten.name;
+        let name = if meldungsdaten.name.is_empty() {
+            name
+        } else {
+            meldungsdaten.name
+        };
+        let mut gemeldete_jahre = gemeldete_jahre(&meldungsdaten.meldungen);
 
         let mut transaktionen = transaktionen.into_iter().peekable();
         let mut meldungen = meldungsdaten.meldungen.into_iter().peekable();
 
+        let schlüssel = (depot.clone(), isin.clone());
         let mut jahre = vec![];
         let mut bestand = Bestand::default();
         let mut meldung = None;
@@ -224,6 +754,7 @@ impl Rechner {
                                 melde_id: nächste_meldung.melde_id,
                             },
                             steuer,
+                            fremdwährung: None,
                         };
                         transaktion_anfügen(&mut jahre, transaktion);
                     } else {
@@ -239,8 +770,11 @@ impl Rechner {
                             meldung = meldungen.next();
                             break;
                         } else {
-                            dbg!(name, bestand, nächste_transaktion, nächste_meldung);
-                            panic!("meldung ohne Ausschüttung");
+                            anyhow::bail!(
+                                "Meldung für `{isin}` vom {} ohne zugehörige Ausschüttung, bitte die \
+                                 `ausschüttung` angeben",
+                                nächste_meldung.datum
+                            );
                         }
                     }
                 } // else: überspringen
@@ -253,13 +787,50 @@ impl Rechner {
             };
 
             let mut datum = transaktion.datum();
+            let mut fremdwährung = None;
 
             let (bestand_, typ, steuer) = match transaktion {
-                format::Transaktion::Kauf(_, format::Zahl(stück), format::Zahl(preis), _) => {
-                    kauf_berechnen(bestand, stück, preis)
+                format::Transaktion::Kauf(_, format::Zahl(stück), preis, spesen, None) => {
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    let (spesen, _) = self.in_euro(spesen.unwrap_or_default(), datum, 2).await?;
+                    if datum < typ.stichtag_neubestand() {
+                        kauf_altbestand_berechnen(bestand, stück, preis, spesen)
+                    } else {
+                        kauf_berechnen(bestand, stück, preis, spesen)
+                    }
                 }
-                format::Transaktion::Verkauf(_, format::Zahl(stück), format::Zahl(preis), _) => {
-                    verkauf_berechnen(bestand, stück, preis)
+                format::Transaktion::Verkauf(_, format::Zahl(stück), preis, spesen, None) => {
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    let (spesen, _) = self.in_euro(spesen.unwrap_or_default(), datum, 2).await?;
+                    verkauf_berechnen(bestand, stück, preis, spesen)
+                }
+                format::Transaktion::Ausbuchung(_, preis) => {
+                    let (preis, währung) =
+                        self.in_euro(preis.unwrap_or_default(), datum, 4).await?;
+                    fremdwährung = währung;
+                    ausbuchung_berechnen(bestand, preis)
+                }
+
+                format::Transaktion::Fondsverschmelzung(
+                    _,
+                    neue_isin,
+                    format::Zahl(faktor),
+                    neues_symbol,
+                ) => {
+                    // ab der Verschmelzung gelten die Meldungen des übernehmenden Fonds
+                    let neue_meldungen = self.meldungen.fetch_meldungen(&neue_isin).await?;
+                    let neue_meldungen: Vec<_> = neue_meldungen
+                        .meldungen
+                        .into_iter()
+                        .filter(|m| m.datum > datum)
+                        .collect();
+                    gemeldete_jahre.extend(self::gemeldete_jahre(&neue_meldungen));
+                    meldungen = neue_meldungen.into_iter().peekable();
+
+                    let neues_symbol = self.fond_symbol(&neue_isin, neues_symbol).await?;
+                    fondsverschmelzung_berechnen(bestand, neue_isin, neues_symbol, faktor)
                 }
 
                 format::Transaktion::Ausschüttung(_, format::Zahl(auszahlung)) => {
@@ -272,6 +843,27 @@ impl Rechner {
                     ausschüttung_berechnen(bestand, auszahlung, meldung)
                 }
 
+                format::Transaktion::Depotausgang(_, format::Zahl(stück), ziel) => {
+                    self.depotausgang(bestand, &schlüssel, datum, stück, ziel)?
+                }
+                format::Transaktion::Depoteingang(_, format::Zahl(stück), quelle) => {
+                    self.depoteingang(bestand, &schlüssel, datum, stück, quelle)?
+                }
+                format::Transaktion::Unentgeltlich(_, format::Zahl(stück), preis, anschaffung) => {
+                    let (preis, währung) = self.in_euro(preis, anschaffung, 4).await?;
+                    fremdwährung = währung;
+                    let altbestand = anschaffung < typ.stichtag_neubestand();
+                    unentgeltlicher_erwerb_berechnen(bestand, stück, preis, anschaffung, altbestand)
+                }
+                format::Transaktion::Schenkung(_, format::Zahl(stück)) => {
+                    anyhow::ensure!(
+                        stück <= bestand.stück,
+                        "Schenkung von {stück} Stück, bei nur {} Stück Bestand",
+                        bestand.stück
+                    );
+                    schenkung_berechnen(bestand, stück)
+                }
+
                 transaktion => {
                     anyhow::bail!("Transaktion `{transaktion:?}` bei ETF nicht möglich");
                 }
@@ -282,158 +874,1018 @@ impl Rechner {
                 bestand,
                 typ,
                 steuer,
+                fremdwährung,
             };
             transaktion_anfügen(&mut jahre, transaktion);
         }
 
         jahre_abschließen(&mut jahre, self.heute.year());
 
-        Ok(Wertpapier {
+        let wertpapier = Wertpapier {
             typ,
             name,
             isin,
             symbol,
+            depot,
             jahre,
+        };
+
+        // Für abgeschlossene Jahre ohne Meldung, in denen Anteile gehalten wurden, gelten die
+        // ausschüttungsgleichen Erträge wie bei einem Nichtmeldefonds als pauschal geschätzt.
+        let heute = self.heute;
+        self.pauschal_schätzen(wertpapier, |jahr| {
+            let bestand = !jahr.bestand_anfang.stück.is_zero()
+                || !jahr.bestand_ende.stück.is_zero()
+                || !jahr.transaktionen.is_empty();
+            bestand && jahr.letzter() <= heute && !gemeldete_jahre.contains(&jahr.jahr)
         })
+        .await
     }
 
-    pub async fn fond_auswerten(&mut self, mut wertpapier: Wertpapier) -> Result<Wertpapier> {
-        let symbol = wertpapier
-            .symbol
-            .as_deref()
-            .context("Fond sollte ein Symbol haben")?;
+    /// Rechnet einen Betrag zum EZB-Referenzkurs des Tages in EUR um.
+    async fn in_euro(
+        &mut self,
+        betrag: format::Betrag,
+        datum: Datum,
+        stellen: u32,
+    ) -> Result<(Zahl, Option<Fremdwährung>)> {
+        let Some(währung) = betrag.währung else {
+            return Ok((betrag.wert, None));
+        };
+        let kurs = self
+            .währungen
+            .kurs_in_euro(Kurs {
+                wert: 1.into(),
+                währung: währung.clone(),
+                datum,
+            })
+            .await?
+            .recip();
 
-        let mut korrektur = Zahl::default();
-        let mut wert_anfang = Zahl::default();
-        for jahr in &mut wertpapier.jahre {
-            jahr.bestand_anfang.preis += korrektur;
+        let wert = runde(betrag.wert / kurs, stellen);
+        let fremdwährung = Fremdwährung {
+            währung,
+            kurs,
+            betrag: betrag.wert,
+            auszahlung: None,
+        };
+        Ok((wert, Some(fremdwährung)))
+    }
 
-            for transaktion in &mut jahr.transaktionen {
-                transaktion.bestand.preis += korrektur;
-                match &transaktion.typ {
-                    TransaktionsTyp::Kauf { stück, preis } => {
-                        wert_anfang += stück * preis;
-                    }
-                    TransaktionsTyp::Verkauf { .. } => {
-                        // TODO
-                    }
-                    TransaktionsTyp::Ausgliederung { .. } => {
-                        todo!()
-                    }
-                    TransaktionsTyp::Einbuchung { .. } => {
-                        todo!()
-                    }
-                    TransaktionsTyp::Spitzenverwertung { .. } => {
-                        todo!()
-                    }
-                    TransaktionsTyp::Dividende { .. } => {
-                        // transaktion.typ = TransaktionsTyp::Ausschüttung {
-                        //     brutto: *brutto,
-                        //     melde_id: None,
-                        // }
-                    }
-                    TransaktionsTyp::Split { .. } => {}
-                    TransaktionsTyp::Ausschüttung { .. }
-                    | TransaktionsTyp::Jahresmeldung { .. } => todo!(),
+    pub async fn anleihe_auswerten(
+        &mut self,
+        wertpapier: format::Wertpapier,
+    ) -> Result<Wertpapier> {
+        let format::Wertpapier {
+            typ,
+            name,
+            isin,
+            symbol,
+            depot,
+            mut transaktionen,
+            ..
+        } = wertpapier;
+
+        transaktionen.sort_by_key(|t| t.datum());
+
+        let schlüssel = (depot.clone(), isin.clone());
+        let mut jahre = vec![];
+        let mut bestand = Bestand::default();
+
+        for transaktion in transaktionen {
+            let datum = transaktion.datum();
+            let mut fremdwährung = None;
+
+            let (bestand_, typ, steuer) = match transaktion {
+                format::Transaktion::Kauf(_, format::Zahl(nominale), kurs, spesen, stückzinsen) => {
+                    nominale_prüfen("Kauf", nominale, None)?;
+                    let (kurs, währung) = self.in_euro(kurs, datum, 4).await?;
+                    fremdwährung = währung;
+                    let (spesen, _) = self.in_euro(spesen.unwrap_or_default(), datum, 2).await?;
+                    let (stückzinsen, _) = self
+                        .in_euro(stückzinsen.unwrap_or_default(), datum, 2)
+                        .await?;
+                    let altbestand = datum < typ.stichtag_neubestand();
+                    anleihe_kauf_berechnen(bestand, nominale, kurs, stückzinsen, spesen, altbestand)
+                }
+                format::Transaktion::Verkauf(
+                    _,
+                    format::Zahl(nominale),
+                    kurs,
+                    spesen,
+                    stückzinsen,
+                ) => {
+                    nominale_prüfen("Verkauf", nominale, Some(bestand))?;
+                    let (kurs, währung) = self.in_euro(kurs, datum, 4).await?;
+                    fremdwährung = währung;
+                    let (spesen, _) = self.in_euro(spesen.unwrap_or_default(), datum, 2).await?;
+                    let (stückzinsen, _) = self
+                        .in_euro(stückzinsen.unwrap_or_default(), datum, 2)
+                        .await?;
+                    anleihe_verkauf_berechnen(bestand, nominale, kurs, stückzinsen, spesen)
+                }
+                format::Transaktion::Tilgung(_, format::Zahl(nominale), kurs) => {
+                    nominale_prüfen("Tilgung", nominale, Some(bestand))?;
+                    let kurs = kurs.unwrap_or(format::Betrag {
+                        wert: 100.into(),
+                        währung: None,
+                    });
+                    let (kurs, währung) = self.in_euro(kurs, datum, 4).await?;
+                    fremdwährung = währung;
+                    tilgung_berechnen(bestand, nominale, kurs)
+                }
+                format::Transaktion::Kupon(_, betrag) => {
+                    let (betrag, währung) = self.in_euro(betrag, datum, 2).await?;
+                    fremdwährung = währung;
+                    kupon_berechnen(bestand, betrag)
+                }
+                format::Transaktion::Ausbuchung(_, kurs) => {
+                    let (kurs, währung) = self.in_euro(kurs.unwrap_or_default(), datum, 4).await?;
+                    fremdwährung = währung;
+                    ausbuchung_berechnen(bestand, kurs)
                 }
-            }
 
-            let datum = jahr.letzter();
-            dbg!(datum, self.heute, korrektur);
-            if datum > self.heute {
-                continue;
-            }
-            let mut bestand = jahr.bestand_ende;
-            if bestand.stück.is_zero() {
-                wert_anfang = Zahl::default();
-                continue;
-            }
+                // Überträge werden ebenfalls mit der Nominale angegeben
+                format::Transaktion::Depotausgang(_, format::Zahl(nominale), ziel) => {
+                    nominale_prüfen("Depotausgang", nominale, Some(bestand))?;
+                    self.depotausgang(bestand, &schlüssel, datum, nominale / 100, ziel)?
+                }
+                format::Transaktion::Depoteingang(_, format::Zahl(nominale), quelle) => {
+                    nominale_prüfen("Depoteingang", nominale, None)?;
+                    self.depoteingang(bestand, &schlüssel, datum, nominale / 100, quelle)?
+                }
+                format::Transaktion::Unentgeltlich(
+                    _,
+                    format::Zahl(nominale),
+                    kurs,
+                    anschaffung,
+                ) => {
+                    nominale_prüfen("Unentgeltlicher Erwerb", nominale, None)?;
+                    let (kurs, währung) = self.in_euro(kurs, anschaffung, 4).await?;
+                    fremdwährung = währung;
+                    let altbestand = anschaffung < typ.stichtag_neubestand();
+                    let stück = nominale / 100;
+                    unentgeltlicher_erwerb_berechnen(bestand, stück, kurs, anschaffung, altbestand)
+                }
+                format::Transaktion::Schenkung(_, format::Zahl(nominale)) => {
+                    nominale_prüfen("Schenkung", nominale, Some(bestand))?;
+                    schenkung_berechnen(bestand, nominale / 100)
+                }
 
-            // jahres schlusskurs abrufen
-            let kurse = self.kursabfrage.kurse_abrufen(symbol, datum).await?;
-            let idx = match kurse.binary_search_by_key(&datum, |daten| daten.datum) {
-                Ok(idx) => idx,
-                Err(0) => 0,
-                Err(idx) => idx - 1,
+                transaktion => {
+                    anyhow::bail!("Transaktion `{transaktion:?}` bei Anleihe nicht möglich");
+                }
             };
-            let kurs = kurse.into_iter().nth(idx).unwrap();
-            let kurs = self
-                .währungen
-                .kurs_in_euro(Kurs {
-                    wert: kurs.close,
-                    währung: kurs.währung,
-                    datum: kurs.datum,
-                })
-                .await?;
-            let wert_ende = bestand.stück * kurs;
-
-            let (_korrektur, typ, steuer) =
-                ausschüttungsgleiche_beträge_berechnen(bestand.stück, wert_anfang, wert_ende);
-            bestand.preis += _korrektur;
-            korrektur += _korrektur;
+            bestand = bestand_;
             let transaktion = Transaktion {
                 datum,
                 bestand,
                 typ,
                 steuer,
+                fremdwährung,
             };
-
-            jahr.transaktionen.push(transaktion);
-            wert_anfang = wert_ende;
-            jahr.bestand_ende = bestand;
+            transaktion_anfügen(&mut jahre, transaktion);
         }
 
-        Ok(wertpapier)
+        jahre_abschließen(&mut jahre, self.heute.year());
+
+        Ok(Wertpapier {
+            typ,
+            name,
+            isin,
+            symbol,
+            depot,
+            jahre,
+        })
     }
-}
 
-fn transaktion_anfügen(jahre: &mut Vec<Jahr>, transaktion: Transaktion) {
-    let jahr = transaktion.datum.year();
-    jahre_abschließen(jahre, jahr);
-    let jahr = if let Some(letztes_jahr) = jahre.last_mut() {
-        if letztes_jahr.jahr < jahr {
-            let bestand = letztes_jahr.bestand_ende;
+    pub async fn krypto_auswerten(&mut self, wertpapier: format::Wertpapier) -> Result<Wertpapier> {
+        let format::Wertpapier {
+            typ,
+            name,
+            isin,
+            symbol,
+            depot,
+            mut transaktionen,
+            ..
+        } = wertpapier;
 
-            jahre.push(Jahr {
-                jahr,
-                bestand_anfang: bestand,
-                ..Default::default()
-            });
-            jahre.last_mut().unwrap()
-        } else {
-            letztes_jahr
-        }
-    } else {
-        jahre.push(Jahr {
-            jahr,
-            ..Default::default()
-        });
-        jahre.last_mut().unwrap()
-    };
-    jahr.bestand_ende = transaktion.bestand;
-    jahr.transaktionen.push(transaktion);
-}
+        let schlüssel = (depot.clone(), isin.clone());
+        transaktionen.sort_by_key(|t| t.datum());
 
-fn jahre_abschließen(jahre: &mut Vec<Jahr>, jahr: i32) {
-    if let Some(letztes_jahr) = jahre.last() {
-        if letztes_jahr.bestand_ende.stück.is_zero() || letztes_jahr.jahr >= jahr {
-            return; // nichts zu tun
-        }
-        let bestand = letztes_jahr.bestand_ende;
-        for jahr in letztes_jahr.jahr + 1..=jahr {
-            jahre.push(Jahr {
-                jahr,
-                bestand_anfang: bestand,
-                bestand_ende: bestand,
-                transaktionen: vec![],
-            })
-        }
-    }
-}
+        let mut jahre = vec![];
+        let mut bestand = Bestand::default();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        for transaktion in transaktionen {
+            let datum = transaktion.datum();
+
+            let ((bestand_, typ, steuer), fremdwährung) = match transaktion {
+                format::Transaktion::Staking(_, format::Zahl(stück), preis) => {
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    (staking_berechnen(bestand, stück, preis), währung)
+                }
+
+                // Kauf, Verkauf und Überträge wie bei Aktien. Laut § 27b (3) 2. ist auch der Tausch
+                // in eine andere Kryptowährung keine Realisierung, die Anschaffungskosten gehen auf
+                // die neue Kryptowährung über.
+                transaktion => {
+                    self.bestand_ändern(typ, bestand, &schlüssel, transaktion)
+                        .await?
+                }
+            };
+            bestand = bestand_;
+            let transaktion = Transaktion {
+                datum,
+                bestand,
+                typ,
+                steuer: krypto_steuer(steuer),
+                fremdwährung,
+            };
+            transaktion_anfügen(&mut jahre, transaktion);
+        }
+
+        jahre_abschließen(&mut jahre, self.heute.year());
+
+        Ok(Wertpapier {
+            typ,
+            name,
+            isin,
+            symbol,
+            depot,
+            jahre,
+        })
+    }
+
+    /// Käufe, Verkäufe, Umtausche und Überträge, die bei Aktien und Kryptowährungen gleich
+    /// berechnet werden.
+    async fn bestand_ändern(
+        &mut self,
+        typ: WertpapierTyp,
+        bestand: Bestand,
+        schlüssel: &Schlüssel,
+        transaktion: format::Transaktion,
+    ) -> Result<(Ergebnis, Option<Fremdwährung>)> {
+        let datum = transaktion.datum();
+        let mut fremdwährung = None;
+
+        let ergebnis = match transaktion {
+            format::Transaktion::Kauf(_, format::Zahl(stück), preis, spesen, None) => {
+                let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                fremdwährung = währung;
+                let (spesen, _) = self.in_euro(spesen.unwrap_or_default(), datum, 2).await?;
+                if datum < typ.stichtag_neubestand() {
+                    kauf_altbestand_berechnen(bestand, stück, preis, spesen)
+                } else {
+                    kauf_berechnen(bestand, stück, preis, spesen)
+                }
+            }
+            format::Transaktion::Verkauf(_, format::Zahl(stück), preis, spesen, None) => {
+                let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                fremdwährung = währung;
+                let (spesen, _) = self.in_euro(spesen.unwrap_or_default(), datum, 2).await?;
+                verkauf_berechnen(bestand, stück, preis, spesen)
+            }
+
+            format::Transaktion::Umtausch(
+                _,
+                format::Zahl(stück),
+                ziel,
+                format::Zahl(neue_stück),
+                zuzahlung,
+            ) => {
+                let (ergebnis, währung) = self
+                    .umtausch(
+                        bestand,
+                        schlüssel,
+                        datum,
+                        stück,
+                        ziel,
+                        neue_stück,
+                        zuzahlung,
+                    )
+                    .await?;
+                fremdwährung = währung;
+                ergebnis
+            }
+            format::Transaktion::Umtauscheingang(_, format::Zahl(stück), quelle) => {
+                self.umtauscheingang(bestand, schlüssel, datum, stück, quelle)?
+            }
+
+            format::Transaktion::Depotausgang(_, format::Zahl(stück), ziel) => {
+                self.depotausgang(bestand, schlüssel, datum, stück, ziel)?
+            }
+            format::Transaktion::Depoteingang(_, format::Zahl(stück), quelle) => {
+                self.depoteingang(bestand, schlüssel, datum, stück, quelle)?
+            }
+            format::Transaktion::Unentgeltlich(_, format::Zahl(stück), preis, anschaffung) => {
+                let (preis, währung) = self.in_euro(preis, anschaffung, 4).await?;
+                fremdwährung = währung;
+                let altbestand = anschaffung < typ.stichtag_neubestand();
+                unentgeltlicher_erwerb_berechnen(bestand, stück, preis, anschaffung, altbestand)
+            }
+            format::Transaktion::Schenkung(_, format::Zahl(stück)) => {
+                anyhow::ensure!(
+                    stück <= bestand.stück,
+                    "Schenkung von {stück} Stück, bei nur {} Stück Bestand",
+                    bestand.stück
+                );
+                schenkung_berechnen(bestand, stück)
+            }
+
+            transaktion => {
+                anyhow::bail!("Transaktion `{transaktion:?}` bei {typ} nicht möglich");
+            }
+        };
+        Ok((ergebnis, fremdwährung))
+    }
+
+    pub async fn derivat_auswerten(
+        &mut self,
+        wertpapier: format::Wertpapier,
+    ) -> Result<Wertpapier> {
+        let format::Wertpapier {
+            typ,
+            name,
+            isin,
+            symbol,
+            depot,
+            mut transaktionen,
+            ..
+        } = wertpapier;
+
+        transaktionen.sort_by_key(|t| t.datum());
+
+        let mut jahre = vec![];
+        let mut bestand = Bestand::default();
+
+        for transaktion in transaktionen {
+            let datum = transaktion.datum();
+            let mut fremdwährung = None;
+
+            let (bestand_, typ, steuer) = match transaktion {
+                format::Transaktion::Kauf(_, format::Zahl(stück), preis, spesen, None) => {
+                    anyhow::ensure!(
+                        bestand.stück >= Zahl::zero(),
+                        "Kauf bei offener Stillhalterposition, bitte als `glattstellung` angeben"
+                    );
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    let (spesen, _) = self.in_euro(spesen.unwrap_or_default(), datum, 2).await?;
+                    if datum < typ.stichtag_neubestand() {
+                        kauf_altbestand_berechnen(bestand, stück, preis, spesen)
+                    } else {
+                        kauf_berechnen(bestand, stück, preis, spesen)
+                    }
+                }
+                format::Transaktion::Verkauf(_, format::Zahl(stück), preis, spesen, None) => {
+                    anyhow::ensure!(
+                        stück <= bestand.stück,
+                        "Verkauf von {stück} Stück, bei nur {} Stück Bestand, ein Verkauf als \
+                         Stillhalter ist als `stillhalter` anzugeben",
+                        bestand.stück
+                    );
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    let (spesen, _) = self.in_euro(spesen.unwrap_or_default(), datum, 2).await?;
+                    verkauf_berechnen(bestand, stück, preis, spesen)
+                }
+                format::Transaktion::Stillhalter(_, format::Zahl(stück), preis) => {
+                    anyhow::ensure!(
+                        bestand.stück <= Zahl::zero(),
+                        "Stillhalterposition bei {} gekauften Stück",
+                        bestand.stück
+                    );
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    stillhalter_berechnen(bestand, stück, preis)
+                }
+                format::Transaktion::Glattstellung(_, format::Zahl(stück), preis) => {
+                    anyhow::ensure!(
+                        stück <= -bestand.stück,
+                        "Glattstellung von {stück} Stück, bei nur {} Stück Stillhalterposition",
+                        -bestand.stück
+                    );
+                    let (preis, währung) = self.in_euro(preis, datum, 4).await?;
+                    fremdwährung = währung;
+                    glattstellung_berechnen(bestand, stück, preis)
+                }
+                format::Transaktion::Verfall(_) => {
+                    anyhow::ensure!(!bestand.stück.is_zero(), "Verfall ohne offene Position");
+                    verfall_berechnen(bestand)
+                }
+                format::Transaktion::Ausübung(
+                    _,
+                    format::Zahl(stück),
+                    art,
+                    basiswert,
+                    basispreis,
+                ) => {
+                    let offen = if bestand.stück < Zahl::zero() {
+                        -bestand.stück
+                    } else {
+                        bestand.stück
+                    };
+                    anyhow::ensure!(
+                        !stück.is_zero() && stück <= offen,
+                        "Ausübung von {stück} Stück, bei nur {offen} Stück offener Position",
+                    );
+                    let (basispreis, währung) = self.in_euro(basispreis, datum, 4).await?;
+                    fremdwährung = währung;
+                    let (ergebnis, andienung) =
+                        ausübung_berechnen(bestand, stück, art, basiswert.clone(), basispreis);
+                    self.überträge
+                        .entry((depot.clone(), basiswert))
+                        .or_default()
+                        .push(Übertrag {
+                            datum,
+                            quelle: Quelle::Ausübung {
+                                derivat: isin.clone(),
+                                kauf: andienung.kauf,
+                            },
+                            bestand: Bestand {
+                                stück: andienung.stück,
+                                preis: andienung.preis,
+                                ..Default::default()
+                            },
+                        });
+                    ergebnis
+                }
+
+                transaktion => {
+                    anyhow::bail!("Transaktion `{transaktion:?}` bei Derivat nicht möglich");
+                }
+            };
+            bestand = bestand_;
+            let transaktion = Transaktion {
+                datum,
+                bestand,
+                typ,
+                steuer: derivat_steuer(steuer),
+                fremdwährung,
+            };
+            transaktion_anfügen(&mut jahre, transaktion);
+        }
+
+        jahre_abschließen(&mut jahre, self.heute.year());
+
+        Ok(Wertpapier {
+            typ,
+            name,
+            isin,
+            symbol,
+            depot,
+            jahre,
+        })
+    }
+
+    pub async fn fond_auswerten(&mut self, wertpapier: Wertpapier) -> Result<Wertpapier> {
+        self.pauschal_schätzen(wertpapier, |_| true).await
+    }
+
+    /// Schätzt in den ausgewählten Jahren die ausschüttungsgleichen Erträge pauschal, und
+    /// berechnet die Transaktionen mit den dadurch erhöhten Anschaffungskosten neu.
+    async fn pauschal_schätzen(
+        &mut self,
+        mut wertpapier: Wertpapier,
+        schätzen: impl Fn(&Jahr) -> bool,
+    ) -> Result<Wertpapier> {
+        let mut kurse = BTreeMap::new();
+        for jahr in wertpapier.jahre.iter().filter(|jahr| schätzen(jahr)) {
+            let mut preise = Rücknahmepreise::default();
+            // zu Jahresbeginn gilt der Fond nach den Verschmelzungen der Vorjahre,
+            // zu Jahresende auch nach jenen dieses Jahres
+            let symbol = |bis_jahr| {
+                symbol_nach_jahr(&wertpapier, bis_jahr).with_context(|| {
+                    format!(
+                        "Für die Schätzung im Jahr {} wird ein Symbol für die Rücknahmepreise benötigt",
+                        jahr.jahr
+                    )
+                })
+            };
+
+            // beide Rücknahmepreise werden nur für die Schätzung zum Jahresende benötigt,
+            // die im laufenden Jahr noch nicht erfolgt
+            let datum = jahr.letzter();
+            if datum <= self.heute && !jahr.bestand_anfang.stück.is_zero() {
+                let symbol = symbol(jahr.jahr - 1)?;
+                preise.erster = Some(self.schlusskurs(symbol, jahr.erster(), true).await?);
+            }
+            if datum <= self.heute && !jahr.bestand_ende.stück.is_zero() {
+                let symbol = symbol(jahr.jahr)?;
+                preise.letzter = Some(self.schlusskurs(symbol, datum, false).await?);
+            }
+            kurse.insert(jahr.jahr, preise);
+        }
+        if kurse.is_empty() {
+            return Ok(wertpapier);
+        }
+
+        let ausgänge = fond_neu_berechnen(&mut wertpapier, &kurse, self.heute)?;
+
+        // die Überträge an andere Depots und Wertpapiere mit den korrigierten
+        // Anschaffungskosten aktualisieren
+        for ausgang in ausgänge {
+            let übertrag = self.überträge.get_mut(&ausgang.ziel).and_then(|überträge| {
+                überträge
+                    .iter_mut()
+                    .find(|ü| ü.datum == ausgang.datum && ü.quelle == ausgang.quelle)
+            });
+            if let Some(übertrag) = übertrag {
+                übertrag.bestand = ausgang.bestand;
+            }
+        }
+
+        Ok(wertpapier)
+    }
+
+    /// Das Symbol des übernehmenden Fonds bei einer Verschmelzung, falls nicht angegeben über
+    /// die ISIN gesucht.
+    async fn fond_symbol(&self, isin: &str, symbol: Option<String>) -> Result<Option<String>> {
+        if symbol.is_some() {
+            return Ok(symbol);
+        }
+        let metadaten = self.kursabfrage.aktie_suchen(isin).await?;
+        Ok(metadaten.map(|metadaten| metadaten.symbol))
+    }
+
+    /// Der Schlusskurs in EUR am Datum, oder am ersten Handelstag danach bzw. letzten davor.
+    async fn schlusskurs(&mut self, symbol: &str, datum: Datum, danach: bool) -> Result<Zahl> {
+        let kurse = self.kursabfrage.kurse_abrufen(symbol, datum).await?;
+        let idx = match kurse.binary_search_by_key(&datum, |daten| daten.datum) {
+            Ok(idx) => idx,
+            Err(idx) if danach => idx,
+            Err(0) => 0,
+            Err(idx) => idx - 1,
+        };
+        let kurs = kurse
+            .into_iter()
+            .nth(idx)
+            .with_context(|| format!("Kein Kurs für `{symbol}` am {datum}"))?;
+        self.währungen
+            .kurs_in_euro(Kurs {
+                wert: kurs.close,
+                währung: kurs.währung,
+                datum: kurs.datum,
+            })
+            .await
+    }
+}
+
+/// Stellt sicher, dass die Nominale einer Anleihe größer Null ist, und bei Abgängen den Bestand
+/// nicht übersteigt.
+fn nominale_prüfen(was: &str, nominale: Zahl, bestand: Option<Bestand>) -> Result<()> {
+    anyhow::ensure!(
+        nominale > Zahl::zero(),
+        "{was} von Nominale {nominale}, diese muss größer Null sein"
+    );
+    if let Some(bestand) = bestand {
+        anyhow::ensure!(
+            nominale <= bestand.stück * 100,
+            "{was} von Nominale {nominale}, bei nur {} Bestand",
+            bestand.stück * 100
+        );
+    }
+    Ok(())
+}
+
+/// Die Jahre, für die ein Fond Meldungen veröffentlicht hat. Laut § 186 (2) Z 2 InvFG ist die
+/// Jahresmeldung innerhalb von sieben Monaten nach Ende des Geschäftsjahres zu übermitteln,
+/// eine Jahresmeldung gilt daher auch für das Jahr, in dem das Geschäftsjahr geendet haben kann.
+fn gemeldete_jahre(meldungen: &[FondMeldung]) -> BTreeSet<i32> {
+    const MELDEFRIST: Months = Months::new(7);
+
+    let mut jahre = BTreeSet::new();
+    for meldung in meldungen {
+        jahre.insert(meldung.datum.year());
+        if meldung.ist_jahresmeldung {
+            let geschäftsjahr_ende = meldung.datum - MELDEFRIST;
+            jahre.insert(geschäftsjahr_ende.year());
+        }
+    }
+    jahre
+}
+
+/// Das Symbol für die Rücknahmepreise nach allen Fondsverschmelzungen bis einschließlich des
+/// angegebenen Jahres.
+fn symbol_nach_jahr(wertpapier: &Wertpapier, jahr: i32) -> Option<&str> {
+    wertpapier
+        .jahre
+        .iter()
+        .take_while(|j| j.jahr <= jahr)
+        .flat_map(|j| &j.transaktionen)
+        .fold(
+            wertpapier.symbol.as_deref(),
+            |bisher, transaktion| match &transaktion.typ {
+                TransaktionsTyp::Fondsverschmelzung { symbol, .. } => symbol.as_deref(),
+                _ => bisher,
+            },
+        )
+}
+
+/// Ein Abgang eines Fonds an ein anderes Depot oder Wertpapier, mit den korrigierten
+/// Anschaffungskosten.
+#[derive(Debug)]
+struct FondAusgang {
+    ziel: Schlüssel,
+    quelle: Quelle,
+    datum: Datum,
+    bestand: Bestand,
+}
+
+/// Der erste und letzte Rücknahmepreis eines Jahres in EUR, soweit diese für die
+/// Schätzung der ausschüttungsgleichen Erträge benötigt werden.
+#[derive(Debug, Default, Clone, Copy)]
+struct Rücknahmepreise {
+    erster: Option<Zahl>,
+    letzter: Option<Zahl>,
+}
+
+/// Berechnet die Transaktionen eines Fonds neu, wobei in den Jahren mit angegebenen Rücknahmepreisen
+/// zum Jahresende und bei jedem Verkauf die ausschüttungsgleichen Erträge geschätzt werden.
+/// Diese erhöhen die Anschaffungskosten, und damit auch die Ergebnisse späterer Verkäufe.
+fn fond_neu_berechnen(
+    wertpapier: &mut Wertpapier,
+    kurse: &BTreeMap<i32, Rücknahmepreise>,
+    heute: Datum,
+) -> Result<Vec<FondAusgang>> {
+    let Wertpapier {
+        typ,
+        isin,
+        depot,
+        jahre,
+        ..
+    } = wertpapier;
+    let stichtag = typ.stichtag_neubestand();
+
+    let mut ausgänge = vec![];
+    let mut bestand = Bestand::default();
+    let mut ursprünglich = Bestand::default();
+
+    for jahr in jahre {
+        jahr.bestand_anfang = bestand;
+        let preise = kurse.get(&jahr.jahr);
+
+        let erster_kurs = if bestand.stück.is_zero() || jahr.letzter() > heute {
+            None
+        } else if let Some(preise) = preise {
+            let kurs = preise
+                .erster
+                .with_context(|| format!("Kein Rücknahmepreis zum {}", jahr.erster()))?;
+            Some(kurs)
+        } else {
+            None
+        };
+        let mut ausgangswert = erster_kurs.map_or(Zahl::zero(), |kurs| bestand.stück * kurs);
+
+        let mut transaktionen = Vec::with_capacity(jahr.transaktionen.len());
+        for mut transaktion in std::mem::take(&mut jahr.transaktionen) {
+            let vorher = bestand;
+            let ursprünglich_vorher = std::mem::replace(&mut ursprünglich, transaktion.bestand);
+            let datum = transaktion.datum;
+
+            let (neu, steuer) = match &transaktion.typ {
+                TransaktionsTyp::Kauf {
+                    stück,
+                    preis,
+                    spesen,
+                } => {
+                    let (neu, _, steuer) = if datum < stichtag {
+                        kauf_altbestand_berechnen(bestand, *stück, *preis, *spesen)
+                    } else {
+                        kauf_berechnen(bestand, *stück, *preis, *spesen)
+                    };
+                    (neu, steuer)
+                }
+                // Zugänge, deren Anschaffungskosten nicht vom bisherigen Bestand abhängen
+                TransaktionsTyp::Einbuchung { .. }
+                | TransaktionsTyp::Depoteingang { .. }
+                | TransaktionsTyp::Umtauscheingang { .. }
+                | TransaktionsTyp::UnentgeltlicherErwerb { .. } => {
+                    let zugang = differenz(ursprünglich_vorher, transaktion.bestand);
+                    (zugang_berechnen(bestand, zugang), transaktion.steuer)
+                }
+
+                TransaktionsTyp::Verkauf {
+                    stück,
+                    preis,
+                    spesen,
+                } if preise.is_some() => {
+                    let (korrektur, typ, steuer) = verkauf_schätzung_berechnen(*stück, *preis);
+                    transaktionen.push(Transaktion {
+                        datum,
+                        bestand,
+                        typ,
+                        steuer,
+                        fremdwährung: None,
+                    });
+
+                    let mut erhöht = bestand;
+                    erhöht.preis += korrektur;
+                    if !erhöht.alt.stück.is_zero() {
+                        erhöht.alt.preis += korrektur;
+                    }
+                    let (mut neu, _, steuer) = verkauf_berechnen(erhöht, *stück, *preis, *spesen);
+
+                    // die verbleibenden Anteile behalten ihre Anschaffungskosten
+                    if !neu.neu_stück().is_zero() {
+                        neu.preis = bestand.preis;
+                    }
+                    if !neu.alt.stück.is_zero() {
+                        neu.alt.preis = bestand.alt.preis;
+                    }
+                    (neu, steuer)
+                }
+                TransaktionsTyp::Verkauf {
+                    stück,
+                    preis,
+                    spesen,
+                } => {
+                    let (neu, _, steuer) = verkauf_berechnen(bestand, *stück, *preis, *spesen);
+                    (neu, steuer)
+                }
+                TransaktionsTyp::Spitzenverwertung { stück, preis } => {
+                    let (neu, _, steuer) = spitzenverwertung_berechnen(bestand, *stück, *preis);
+                    (neu, steuer)
+                }
+                TransaktionsTyp::Ausbuchung { preis, .. } => {
+                    let (neu, _, steuer) = ausbuchung_berechnen(bestand, *preis);
+                    (neu, steuer)
+                }
+                TransaktionsTyp::Schenkung { stück } => {
+                    let (neu, _, steuer) = schenkung_berechnen(bestand, *stück);
+                    (neu, steuer)
+                }
+                TransaktionsTyp::Depotausgang {
+                    stück,
+                    depot: ziel_depot,
+                } => {
+                    let ((neu, _, steuer), übertrag) =
+                        depotausgang_berechnen(bestand, *stück, ziel_depot.clone());
+                    ausgänge.push(FondAusgang {
+                        ziel: (Some(ziel_depot.clone()), isin.clone()),
+                        quelle: Quelle::Depot(depot.clone()),
+                        datum,
+                        bestand: übertrag,
+                    });
+                    (neu, steuer)
+                }
+                TransaktionsTyp::Umtausch {
+                    stück,
+                    isin: ziel,
+                    neue_stück,
+                    zuzahlung,
+                } => {
+                    let ((neu, _, steuer), übertrag) =
+                        umtausch_berechnen(bestand, *stück, ziel.clone(), *neue_stück, *zuzahlung);
+                    ausgänge.push(FondAusgang {
+                        ziel: (depot.clone(), ziel.clone()),
+                        quelle: Quelle::Wertpapier(isin.clone()),
+                        datum,
+                        bestand: übertrag,
+                    });
+                    (neu, steuer)
+                }
+
+                TransaktionsTyp::Split { faktor } => {
+                    let (neu, _, steuer) = split_berechnen(bestand, *faktor);
+                    (neu, steuer)
+                }
+                TransaktionsTyp::Gratisaktien { stück } => {
+                    let (neu, _, steuer) = gratisaktien_berechnen(bestand, *stück);
+                    (neu, steuer)
+                }
+                TransaktionsTyp::Fondsverschmelzung {
+                    isin,
+                    symbol,
+                    faktor,
+                } => {
+                    let (neu, _, steuer) = fondsverschmelzung_berechnen(
+                        bestand,
+                        isin.clone(),
+                        symbol.clone(),
+                        *faktor,
+                    );
+                    (neu, steuer)
+                }
+
+                // Meldungen korrigieren die Anschaffungskosten um einen Betrag je Stück
+                TransaktionsTyp::Ausschüttung { .. } | TransaktionsTyp::Jahresmeldung { .. } => {
+                    let mut neu = bestand;
+                    neu.preis += transaktion.bestand.preis - ursprünglich_vorher.preis;
+                    if !neu.alt.stück.is_zero() {
+                        neu.alt.preis +=
+                            transaktion.bestand.alt.preis - ursprünglich_vorher.alt.preis;
+                    }
+                    (neu, transaktion.steuer)
+                }
+
+                TransaktionsTyp::Ausgliederung { .. } => {
+                    anyhow::bail!("Fonds haben keine Ausgliederungen");
+                }
+                TransaktionsTyp::Dividende { .. } => {
+                    anyhow::bail!("Ausschüttungen von Fonds sind als `ausschüttung` anzugeben");
+                }
+                TransaktionsTyp::Aktiendividende { .. } => {
+                    anyhow::bail!("Fonds haben keine Aktiendividenden");
+                }
+                TransaktionsTyp::Rückerstattung { .. } => {
+                    anyhow::bail!("Fonds haben keine Rückerstattungen von Quellensteuer");
+                }
+                TransaktionsTyp::Einlagenrückzahlung { .. } => {
+                    anyhow::bail!("Fonds haben keine Einlagenrückzahlungen");
+                }
+                TransaktionsTyp::Bezugsrecht { .. }
+                | TransaktionsTyp::BezugsrechtVerkauf { .. }
+                | TransaktionsTyp::BezugsrechtAusübung { .. } => {
+                    anyhow::bail!("Fonds haben keine Bezugsrechte");
+                }
+                TransaktionsTyp::AnleiheKauf { .. }
+                | TransaktionsTyp::AnleiheVerkauf { .. }
+                | TransaktionsTyp::Tilgung { .. }
+                | TransaktionsTyp::Kupon { .. } => {
+                    anyhow::bail!("Fonds haben keine Tilgungen und Kupons");
+                }
+                TransaktionsTyp::Staking { .. } => {
+                    anyhow::bail!("Fonds haben kein Staking");
+                }
+                TransaktionsTyp::Stillhalterprämie { .. }
+                | TransaktionsTyp::Glattstellung { .. }
+                | TransaktionsTyp::Verfall { .. }
+                | TransaktionsTyp::Ausübung { .. } => {
+                    anyhow::bail!("Fonds haben keine Optionsgeschäfte");
+                }
+                TransaktionsTyp::Schätzung { .. } | TransaktionsTyp::SchätzungVerkauf { .. } => {
+                    anyhow::bail!("Der Fond wurde bereits neu berechnet");
+                }
+            };
+
+            // Der Ausgangswert wird um Zugänge zu deren Anschaffungskosten erhöht,
+            // und bei Abgängen anteilig vermindert.
+            if neu.stück > vorher.stück && neu.summe() > vorher.summe() {
+                ausgangswert += neu.summe() - vorher.summe();
+            } else if neu.stück < vorher.stück {
+                ausgangswert = runde(ausgangswert * neu.stück / vorher.stück, 4);
+            }
+
+            transaktion.bestand = neu;
+            transaktion.steuer = steuer;
+            transaktionen.push(transaktion);
+            bestand = neu;
+        }
+
+        let datum = jahr.letzter();
+        if let Some(preise) = preise.filter(|_| !bestand.stück.is_zero() && datum <= heute) {
+            let letzter_kurs = preise
+                .letzter
+                .with_context(|| format!("Kein Rücknahmepreis zum {datum}"))?;
+
+            let (korrektur, typ, steuer) = ausschüttungsgleiche_beträge_berechnen(
+                bestand.stück,
+                ausgangswert,
+                erster_kurs,
+                letzter_kurs,
+            );
+            bestand.preis += korrektur;
+            if !bestand.alt.stück.is_zero() {
+                bestand.alt.preis += korrektur;
+            }
+
+            transaktionen.push(Transaktion {
+                datum,
+                bestand,
+                typ,
+                steuer,
+                fremdwährung: None,
+            });
+        }
+        jahr.transaktionen = transaktionen;
+        jahr.bestand_ende = bestand;
+    }
+
+    Ok(ausgänge)
+}
+
+/// Der Zugang zwischen zwei Beständen, getrennt nach Neu- und Altbestand.
+fn differenz(vorher: Bestand, nachher: Bestand) -> Bestand {
+    let neu_stück = nachher.neu_stück() - vorher.neu_stück();
+    let alt_stück = nachher.alt.stück - vorher.alt.stück;
+    let preis = |stück: Zahl, summe: Zahl| {
+        if stück.is_zero() {
+            Zahl::zero()
+        } else {
+            summe / stück
+        }
+    };
+    Bestand {
+        stück: neu_stück + alt_stück,
+        preis: preis(
+            neu_stück,
+            nachher.neu_stück() * nachher.preis - vorher.neu_stück() * vorher.preis,
+        ),
+        alt: Altbestand {
+            stück: alt_stück,
+            preis: preis(alt_stück, nachher.alt.summe() - vorher.alt.summe()),
+        },
+        ..Default::default()
+    }
+}
+
+fn bezugsrechte_prüfen(bestand: Bestand, anzahl: Zahl) -> Result<()> {
+    anyhow::ensure!(
+        anzahl <= bestand.bezugsrechte,
+        "{anzahl} Bezugsrechte verwendet, bei nur {} eingebuchten Bezugsrechten",
+        bestand.bezugsrechte
+    );
+    Ok(())
+}
+
+/// Sortiert die Wertpapiere so, dass jene, die Bestand an ein anderes Depot oder
+/// Wertpapier übertragen, vor dem Ziel ausgewertet werden.
+pub fn reihenfolge<T>(
+    wertpapiere: Vec<(T, format::Wertpapier)>,
+) -> Result<Vec<(T, format::Wertpapier)>> {
+    let indizes: HashMap<_, _> = wertpapiere
+        .iter()
+        .enumerate()
+        .map(|(i, (_, w))| ((w.depot.clone(), w.isin.clone()), i))
+        .collect();
+
+    let mut eingänge = vec![0; wertpapiere.len()];
+    let mut kanten = vec![vec![]; wertpapiere.len()];
+    for (i, (_, wertpapier)) in wertpapiere.iter().enumerate() {
+        for ziel in wertpapier.ziele() {
+            if let Some(&j) = indizes.get(&ziel).filter(|&&j| j != i) {
+                kanten[i].push(j);
+                eingänge[j] += 1;
+            }
+        }
+    }
+
+    let mut übrig: Vec<_> = wertpapiere.into_iter().map(Some).collect();
+    let mut sortiert = Vec::with_capacity(übrig.len());
+    while sortiert.len() < übrig.len() {
+        let i = (0..übrig.len())
+            .find(|&i| übrig[i].is_some() && eingänge[i] == 0)
+            .context(
+                "Gegenseitige Überträge zwischen Depots oder Wertpapieren werden nicht unterstützt",
+            )?;
+        for &j in &kanten[i] {
+            eingänge[j] -= 1;
+        }
+        sortiert.push(übrig[i].take().unwrap());
+    }
+
+    Ok(sortiert)
+}
+
+fn transaktion_anfügen(jahre: &mut Vec<Jahr>, transaktion: Transaktion) {
+    let jahr = transaktion.datum.year();
+    jahre_abschließen(jahre, jahr);
+    let jahr = if let Some(letztes_jahr) = jahre.last_mut() {
+        if letztes_jahr.jahr < jahr {
+            let bestand = letztes_jahr.bestand_ende;
+
+            jahre.push(Jahr {
+                jahr,
+                bestand_anfang: bestand,
+                ..Default::default()
+            });
+            jahre.last_mut().unwrap()
+        } else {
+            letztes_jahr
+        }
+    } else {
+        jahre.push(Jahr {
+            jahr,
+            ..Default::default()
+        });
+        jahre.last_mut().unwrap()
+    };
+    jahr.bestand_ende = transaktion.bestand;
+    jahr.transaktionen.push(transaktion);
+}
+
+fn jahre_abschließen(jahre: &mut Vec<Jahr>, jahr: i32) {
+    if let Some(letztes_jahr) = jahre.last() {
+        if letztes_jahr.bestand_ende.stück.is_zero() || letztes_jahr.jahr >= jahr {
+            return; // nichts zu tun
+        }
+        let bestand = letztes_jahr.bestand_ende;
+        for jahr in letztes_jahr.jahr + 1..=jahr {
+            jahre.push(Jahr {
+                jahr,
+                bestand_anfang: bestand,
+                bestand_ende: bestand,
+                transaktionen: vec![],
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Steuer, SteuerAusschüttung};
 
     #[tokio::test]
     async fn test_berechnung() {
@@ -466,4 +1918,519 @@ transaktionen:
         let meituan = rechner.wertpapier_auswerten(meituan).await.unwrap();
         dbg!(&meituan);
     }
+
+    #[tokio::test]
+    async fn anleihe_mit_depotübertrag() {
+        let mut rechner = Rechner::new().await.unwrap();
+
+        let ohne_nominale = serde_yaml::from_str(
+            r#"
+typ: anleihe
+name: Anleihe
+isin: AT0000000001
+transaktionen:
+- kauf: [2023-01-02, 0, 98]
+        "#,
+        )
+        .unwrap();
+        let fehler = rechner.anleihe_auswerten(ohne_nominale).await.unwrap_err();
+        assert!(fehler.to_string().contains("muss größer Null sein"));
+
+        let ausgang = serde_yaml::from_str(
+            r#"
+typ: anleihe
+name: Anleihe
+isin: AT0000000001
+depot: A
+transaktionen:
+- kauf: [2023-01-02, 10000, 98]
+- depotausgang: [2023-03-01, 10000, B]
+        "#,
+        )
+        .unwrap();
+        rechner.anleihe_auswerten(ausgang).await.unwrap();
+        let eingang = serde_yaml::from_str(
+            r#"
+typ: anleihe
+name: Anleihe
+isin: AT0000000001
+depot: B
+transaktionen:
+- depoteingang: [2023-03-01, 10000, A]
+- schenkung: [2023-06-01, 4000]
+        "#,
+        )
+        .unwrap();
+        let eingang = rechner.anleihe_auswerten(eingang).await.unwrap();
+        rechner.offene_überträge_prüfen().unwrap();
+
+        let jahr = &eingang.jahre[0];
+        assert_eq!(jahr.bestand_ende.stück, Zahl::from(60));
+        assert_eq!(jahr.bestand_ende.preis, Zahl::from(98));
+
+        // der Bestand wird als Nominale ausgegeben
+        let mut ausgabe = std::string::String::new();
+        crate::report::schreibe_jahr(&mut ausgabe, WertpapierTyp::Anleihe, jahr).unwrap();
+        assert!(ausgabe.contains("Depotübertrag Nominale 10.000,00 zu 98,0000 % von `A`"));
+        assert!(ausgabe.contains("Unentgeltliche Übertragung Nominale 4.000,00"));
+        assert!(!ausgabe.contains("Stück"));
+    }
+
+    #[tokio::test]
+    async fn krypto_mit_umtausch() {
+        let mut rechner = Rechner::new().await.unwrap();
+
+        let btc = serde_yaml::from_str(
+            r#"
+typ: krypto
+name: Bitcoin
+isin: BTC
+transaktionen:
+- kauf: [2022-01-10, 1/2, 40000]
+- umtausch: [2022-05-01, 1/2, ETH, 15/2]
+        "#,
+        )
+        .unwrap();
+        let btc = rechner.wertpapier_auswerten(btc).await.unwrap();
+        let eth = serde_yaml::from_str(
+            r#"
+typ: krypto
+name: Ethereum
+isin: ETH
+transaktionen:
+- umtauscheingang: [2022-05-01, 15/2, BTC]
+- schenkung: [2022-08-01, 1]
+        "#,
+        )
+        .unwrap();
+        let eth = rechner.wertpapier_auswerten(eth).await.unwrap();
+        rechner.offene_überträge_prüfen().unwrap();
+
+        let bestand = eth.jahre[0].bestand_ende;
+        assert_eq!(bestand.stück, Zahl::new(13, 2));
+        // die Anschaffungskosten von € 20.000 gehen auf die 7,5 ETH über
+        assert_eq!(bestand.preis, Zahl::new(26_666_667, 10_000));
+
+        // Bruchteile werden nur bei Kryptowährungen als Kommazahl ausgegeben
+        let mut ausgabe = std::string::String::new();
+        crate::report::schreibe_jahr(&mut ausgabe, WertpapierTyp::Krypto, &btc.jahre[0]).unwrap();
+        assert!(ausgabe.contains("Umtausch 0,5 Stück in 7,5 Stück `ETH`"));
+        let mut ausgabe = std::string::String::new();
+        crate::report::schreibe_jahr(&mut ausgabe, WertpapierTyp::Aktie, &btc.jahre[0]).unwrap();
+        assert!(ausgabe.contains("Umtausch 1/2 Stück in 7 1/2 Stück `ETH`"));
+    }
+
+    #[tokio::test]
+    async fn verkehrswert_angegeben_oder_abgefragt() {
+        let mut rechner = Rechner::new().await.unwrap();
+        let tag = datum("2023-01-05");
+
+        let angegeben = format::Betrag {
+            wert: Zahl::new(1234, 100),
+            währung: None,
+        };
+        let wert = rechner
+            .verkehrswert(Some(angegeben), "XX0000000000", tag)
+            .await
+            .unwrap();
+        assert_eq!(wert.quelle, Kursquelle::Angegeben);
+        assert_eq!(wert.wert, Zahl::new(1234, 100));
+
+        // ohne Verkehrswerte können die Anschaffungskosten nicht aufgeteilt werden
+        let null = || {
+            Some(format::Betrag {
+                wert: Zahl::zero(),
+                währung: None,
+            })
+        };
+        let bestand = Bestand {
+            stück: 10.into(),
+            preis: 100.into(),
+            ..Default::default()
+        };
+        let schlüssel = (None, String::new("A"));
+        let fehler = rechner
+            .ausgliederung(
+                bestand,
+                &schlüssel,
+                tag,
+                1.into(),
+                "B".into(),
+                null(),
+                null(),
+            )
+            .await
+            .unwrap_err();
+        assert!(fehler
+            .to_string()
+            .contains("ohne Bestand oder Verkehrswert"));
+
+        // die Herkunft und ursprüngliche Währung werden ausgegeben
+        let verkehrswert = |wert: i64, quelle, fremdwährung| Verkehrswert {
+            wert: wert.into(),
+            quelle,
+            fremdwährung,
+        };
+        let usd = Fremdwährung {
+            währung: "USD".into(),
+            kurs: Zahl::new(11, 10),
+            betrag: 44.into(),
+            auszahlung: None,
+        };
+        let bestand = Bestand {
+            stück: 10.into(),
+            preis: 100.into(),
+            ..Default::default()
+        };
+        let ((bestand, typ, steuer), _) = ausgliederung_berechnen(
+            bestand,
+            Zahl::new(1, 10),
+            "B".into(),
+            verkehrswert(40, Kursquelle::Angegeben, Some(usd)),
+            verkehrswert(100, Kursquelle::Abgefragt, None),
+        );
+        let mut jahre = vec![];
+        anfügen(&mut jahre, "2023-01-05", (bestand, typ, steuer));
+        let mut ausgabe = std::string::String::new();
+        crate::report::schreibe_jahr(&mut ausgabe, WertpapierTyp::Aktie, &jahre[0]).unwrap();
+        assert!(ausgabe.contains("Verkehrswert (angegeben):"));
+        assert!(ausgabe.contains("Verkehrswert in USD (1 € = 1,1000 USD):"));
+        assert!(ausgabe.contains("USD 44,0000"));
+        assert!(ausgabe.contains("Verkehrswert `B` (Eröffnungskurs):"));
+    }
+
+    #[test]
+    fn fremdwährung_wie_angegeben() {
+        // 1000 JPY sind zum Kurs 157,23 gerundet 6,36 €, zurückgerechnet aber 999,98 JPY
+        let kurs = Zahl::new(15723, 100);
+        let brutto = runde(Zahl::from(1000) / kurs, 2);
+        let auszahlung = runde(Zahl::from(850) / kurs, 2);
+        let satz = dba::quellensteuersatz(Land::new("JP").unwrap(), None, 2023);
+        let (bestand, typ, steuer) =
+            dividende_berechnen(Bestand::default(), satz, brutto, auszahlung);
+        let transaktion = Transaktion {
+            datum: datum("2023-06-01"),
+            bestand,
+            typ,
+            steuer,
+            fremdwährung: Some(Fremdwährung {
+                währung: "JPY".into(),
+                kurs,
+                betrag: 1000.into(),
+                auszahlung: Some(850.into()),
+            }),
+        };
+        let mut jahre = vec![];
+        transaktion_anfügen(&mut jahre, transaktion);
+        let mut ausgabe = std::string::String::new();
+        crate::report::schreibe_jahr(&mut ausgabe, WertpapierTyp::Aktie, &jahre[0]).unwrap();
+        assert!(ausgabe.contains("JPY 1.000,00"), "{ausgabe}");
+        assert!(ausgabe.contains("JPY 850,00"), "{ausgabe}");
+    }
+
+    fn datum(datum: &str) -> Datum {
+        datum.parse().unwrap()
+    }
+
+    fn anfügen(jahre: &mut Vec<Jahr>, datum: &str, (bestand, typ, steuer): Ergebnis) -> Bestand {
+        let transaktion = Transaktion {
+            datum: self::datum(datum),
+            bestand,
+            typ,
+            steuer,
+            fremdwährung: None,
+        };
+        transaktion_anfügen(jahre, transaktion);
+        bestand
+    }
+
+    #[test]
+    fn test_fond_neu_berechnen() {
+        let mut jahre = vec![];
+        let b = Bestand::default();
+        let b = anfügen(
+            &mut jahre,
+            "2020-03-01",
+            kauf_berechnen(b, 10.into(), 100.into(), 0.into()),
+        );
+        let b = anfügen(
+            &mut jahre,
+            "2021-06-01",
+            verkauf_berechnen(b, 5.into(), 150.into(), 0.into()),
+        );
+        let b = anfügen(
+            &mut jahre,
+            "2021-07-01",
+            ausschüttung_berechnen(b, 20.into(), None),
+        );
+        let b = anfügen(
+            &mut jahre,
+            "2022-02-01",
+            verkauf_berechnen(b, 5.into(), 160.into(), 0.into()),
+        );
+        anfügen(
+            &mut jahre,
+            "2023-01-10",
+            kauf_berechnen(b, 4.into(), 100.into(), 0.into()),
+        );
+        jahre_abschließen(&mut jahre, 2024);
+
+        let mut wertpapier = Wertpapier {
+            typ: WertpapierTyp::Fond,
+            name: "Fond".into(),
+            isin: "AT000".into(),
+            symbol: None,
+            depot: None,
+            jahre,
+        };
+        let preise = |erster: Option<i64>, letzter: Option<i64>| Rücknahmepreise {
+            erster: erster.map(Zahl::from),
+            letzter: letzter.map(Zahl::from),
+        };
+        let kurse = BTreeMap::from([
+            (2020, preise(None, Some(120))),
+            (2021, preise(Some(130), Some(140))),
+            (2022, preise(Some(135), None)),
+            (2023, preise(None, Some(110))),
+            // im laufenden Jahr wird noch kein Rücknahmepreis benötigt
+            (2024, preise(None, None)),
+        ]);
+        fond_neu_berechnen(&mut wertpapier, &kurse, datum("2024-06-01")).unwrap();
+
+        let steuern: Vec<_> = wertpapier
+            .jahre
+            .iter()
+            .flat_map(|j| &j.transaktionen)
+            .map(|t| t.steuer)
+            .collect();
+        let ausschüttungsgleich = |steuer: &Steuer| match steuer {
+            Steuer::Ausschüttung(s) => (s.ausschüttungen_898, s.ausschüttungsgleiche_erträge_937),
+            _ => panic!("Ausschüttung erwartet"),
+        };
+        let überschuss = |steuer: &Steuer| match steuer {
+            Steuer::Verkauf(s) => s.überschüsse_994,
+            _ => panic!("Verkauf erwartet"),
+        };
+
+        // 2020: 90 % von 1200 - 1000, erhöht die Anschaffungskosten um 18 je Stück
+        assert_eq!(ausschüttungsgleich(&steuern[1]), (0.into(), 180.into()));
+        // 2021: 6 % von 750 für die verkauften Stück, erhöht deren Anschaffungskosten um 9
+        assert_eq!(ausschüttungsgleich(&steuern[2]), (0.into(), 45.into()));
+        assert_eq!(überschuss(&steuern[3]), 115.into());
+        assert_eq!(ausschüttungsgleich(&steuern[4]), (20.into(), 0.into()));
+        // mindestens 10 % von 700, da 90 % von 700 - 650 weniger ergibt
+        assert_eq!(ausschüttungsgleich(&steuern[5]), (0.into(), 70.into()));
+        // 2022: der restliche Bestand wird verkauft, mit 6 % von 800
+        assert_eq!(ausschüttungsgleich(&steuern[6]), (0.into(), 48.into()));
+        assert_eq!(überschuss(&steuern[7]), 92.into());
+        // 2023: mindestens 10 % von 440
+        assert_eq!(ausschüttungsgleich(&steuern[9]), (0.into(), 44.into()));
+
+        let letztes = wertpapier.jahre.last().unwrap();
+        assert_eq!(letztes.jahr, 2024);
+        assert_eq!(letztes.bestand_ende.preis, Zahl::from(111));
+    }
+
+    #[test]
+    fn test_etf_ohne_meldung() {
+        let mut jahre = vec![];
+        let b = Bestand::default();
+        let b = anfügen(
+            &mut jahre,
+            "2020-03-01",
+            kauf_berechnen(b, 10.into(), 100.into(), 0.into()),
+        );
+        let mut gemeldet = b;
+        gemeldet.preis += 5;
+        let steuer = SteuerAusschüttung {
+            ausschüttungsgleiche_erträge_937: 50.into(),
+            ..Default::default()
+        };
+        let b = anfügen(
+            &mut jahre,
+            "2021-06-30",
+            (
+                gemeldet,
+                TransaktionsTyp::Jahresmeldung { melde_id: 1 },
+                Steuer::Ausschüttung(steuer),
+            ),
+        );
+        anfügen(
+            &mut jahre,
+            "2022-05-01",
+            verkauf_berechnen(b, 4.into(), 130.into(), 0.into()),
+        );
+        jahre_abschließen(&mut jahre, 2023);
+
+        let mut wertpapier = Wertpapier {
+            typ: WertpapierTyp::Etf,
+            name: "ETF".into(),
+            isin: "IE000".into(),
+            symbol: None,
+            depot: None,
+            jahre,
+        };
+        // nur 2022 gibt es keine Meldung
+        let kurse = BTreeMap::from([(
+            2022,
+            Rücknahmepreise {
+                erster: Some(120.into()),
+                letzter: Some(125.into()),
+            },
+        )]);
+        fond_neu_berechnen(&mut wertpapier, &kurse, datum("2023-06-01")).unwrap();
+
+        let transaktionen: Vec<_> = wertpapier
+            .jahre
+            .iter()
+            .flat_map(|j| &j.transaktionen)
+            .collect();
+        assert_eq!(transaktionen.len(), 5);
+        assert_eq!(transaktionen[1].bestand.preis, Zahl::from(105));
+
+        let ausschüttungsgleich = |steuer: &Steuer| match steuer {
+            Steuer::Ausschüttung(s) => s.ausschüttungsgleiche_erträge_937,
+            _ => panic!("Ausschüttung erwartet"),
+        };
+        // 6 % von 520, erhöht die Anschaffungskosten der verkauften Stück um 7.8
+        assert_eq!(
+            ausschüttungsgleich(&transaktionen[2].steuer),
+            Zahl::new(312, 10)
+        );
+        match transaktionen[3].steuer {
+            Steuer::Verkauf(s) => assert_eq!(s.überschüsse_994, Zahl::new(688, 10)),
+            _ => panic!("Verkauf erwartet"),
+        }
+        // mindestens 10 % von 750, da 90 % von 750 - 720 weniger ergibt
+        assert!(matches!(
+            transaktionen[4].typ,
+            TransaktionsTyp::Schätzung {
+                erster_kurs: Some(_),
+                ..
+            }
+        ));
+        assert_eq!(ausschüttungsgleich(&transaktionen[4].steuer), 75.into());
+
+        let letztes = wertpapier.jahre.last().unwrap();
+        assert_eq!(letztes.bestand_ende.preis, Zahl::new(1175, 10));
+    }
+
+    #[test]
+    fn test_gemeldete_jahre() {
+        let meldung = |datum: &str, ist_jahresmeldung| FondMeldung {
+            datum: self::datum(datum),
+            ist_jahresmeldung,
+            ..Default::default()
+        };
+        // die Jahresmeldung Anfang 2023 betrifft das 2022 geendete Geschäftsjahr
+        let meldungen = [meldung("2021-12-28", true), meldung("2023-01-03", true)];
+        assert_eq!(
+            gemeldete_jahre(&meldungen),
+            BTreeSet::from([2021, 2022, 2023])
+        );
+
+        // ohne Jahresmeldung wurde 2022 nicht gemeldet
+        let meldungen = [
+            meldung("2021-12-28", true),
+            meldung("2023-01-03", false),
+            meldung("2023-09-01", true),
+        ];
+        assert_eq!(gemeldete_jahre(&meldungen), BTreeSet::from([2021, 2023]));
+
+        // nach einer Jahresmeldung im Herbst folgt ein Jahr ohne Meldung
+        let meldungen = [meldung("2020-10-15", true), meldung("2022-10-15", true)];
+        assert_eq!(gemeldete_jahre(&meldungen), BTreeSet::from([2020, 2022]));
+    }
+
+    #[test]
+    fn test_fondsverschmelzung() {
+        let mut jahre = vec![];
+        let b = Bestand::default();
+        let b = anfügen(
+            &mut jahre,
+            "2020-03-01",
+            kauf_berechnen(b, 10.into(), 100.into(), 0.into()),
+        );
+        anfügen(
+            &mut jahre,
+            "2021-06-01",
+            fondsverschmelzung_berechnen(b, "AT111".into(), Some("NEU".into()), 2.into()),
+        );
+        jahre_abschließen(&mut jahre, 2022);
+
+        let mut wertpapier = Wertpapier {
+            typ: WertpapierTyp::Fond,
+            name: "Fond".into(),
+            isin: "AT000".into(),
+            symbol: Some("ALT".into()),
+            depot: None,
+            jahre,
+        };
+        // der erste Rücknahmepreis 2021 ist noch jener des alten Fonds
+        assert_eq!(symbol_nach_jahr(&wertpapier, 2020), Some("ALT"));
+        assert_eq!(symbol_nach_jahr(&wertpapier, 2021), Some("NEU"));
+        assert_eq!(symbol_nach_jahr(&wertpapier, 2022), Some("NEU"));
+
+        let kurse = BTreeMap::from([
+            (
+                2020,
+                Rücknahmepreise {
+                    erster: None,
+                    letzter: Some(110.into()),
+                },
+            ),
+            (
+                2021,
+                Rücknahmepreise {
+                    erster: Some(112.into()),
+                    letzter: Some(60.into()),
+                },
+            ),
+        ]);
+        fond_neu_berechnen(&mut wertpapier, &kurse, datum("2022-06-01")).unwrap();
+
+        // 2020: mindestens 10 % von 1100, erhöht die Anschaffungskosten um 11 je Stück
+        let verschmelzung = &wertpapier.jahre[1].transaktionen[0];
+        assert!(matches!(
+            verschmelzung.typ,
+            TransaktionsTyp::Fondsverschmelzung { .. }
+        ));
+        // die Anschaffungskosten gehen im Umtauschverhältnis auf die neuen Anteile über
+        assert_eq!(verschmelzung.bestand.stück, Zahl::from(20));
+        assert_eq!(verschmelzung.bestand.preis, Zahl::new(555, 10));
+        // 2021: mindestens 10 % von 20 * 60, erhöht die Anschaffungskosten um 6 je Stück
+        assert_eq!(wertpapier.jahre[1].bestand_ende.stück, Zahl::from(20));
+        assert_eq!(wertpapier.jahre[1].bestand_ende.preis, Zahl::new(615, 10));
+    }
+
+    #[test]
+    fn test_reihenfolge() {
+        let ziel: format::Wertpapier = serde_yaml::from_str(
+            r#"
+typ: etf
+name: Foo
+isin: DE000
+depot: B
+transaktionen:
+- depoteingang: [2023-02-01, 10, A]
+        "#,
+        )
+        .unwrap();
+        let quelle: format::Wertpapier = serde_yaml::from_str(
+            r#"
+typ: etf
+name: Foo
+isin: DE000
+depot: A
+transaktionen:
+- kauf: [2023-01-01, 10, 100]
+- depotausgang: [2023-02-01, 10, B]
+        "#,
+        )
+        .unwrap();
+
+        let sortiert = reihenfolge(vec![("ziel", ziel), ("quelle", quelle)]).unwrap();
+        let sortiert: Vec<_> = sortiert.iter().map(|(name, _)| *name).collect();
+        assert_eq!(sortiert, ["quelle", "ziel"]);
+    }
 }
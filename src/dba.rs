@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::Result;
+use chrono::Datelike;
+use num_traits::Zero;
+
+use crate::{Datum, Steuer, Transaktion, TransaktionsTyp, Zahl};
+
+/// Ein Land, als Ländercode laut ISO 3166, wie es auch die ersten beiden Zeichen einer ISIN sind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Land([u8; 2]);
+
+impl Land {
+    pub const ÖSTERREICH: Land = Land(*b"AT");
+
+    pub fn new(code: &str) -> Result<Self> {
+        let code = code.trim().as_bytes();
+        anyhow::ensure!(
+            code.len() == 2 && code.iter().all(u8::is_ascii_alphabetic),
+            "`{}` ist kein gültiger Ländercode",
+            String::from_utf8_lossy(code)
+        );
+        Ok(Land([
+            code[0].to_ascii_uppercase(),
+            code[1].to_ascii_uppercase(),
+        ]))
+    }
+
+    /// Das Land laut ISIN-Präfix, oder das für das Wertpapier angegebene Land, etwa bei ADRs
+    /// oder bei Unternehmen mit Sitz in einem anderen Land.
+    pub fn bestimmen(isin: &str, land: Option<&str>) -> Result<Self> {
+        match land {
+            Some(land) => Land::new(land),
+            None => Land::new(isin.get(..2).unwrap_or(isin)),
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap_or("??")
+    }
+}
+
+impl fmt::Display for Land {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Woher der anrechenbare Quellensteuersatz stammt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Satzquelle {
+    /// Inländische Dividenden, die KeSt wird zur Gänze angerechnet
+    Inland,
+    /// Satz laut Doppelbesteuerungsabkommen
+    Dba,
+    /// Für das Wertpapier angegebener Satz
+    Angegeben,
+    /// Kein Abkommen in der Tabelle, es wird pauschal 15% angenommen
+    Pauschal,
+}
+
+/// Der anrechenbare Quellensteuersatz für Dividenden eines Wertpapiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quellensteuersatz {
+    pub land: Land,
+    pub satz: Zahl,
+    pub quelle: Satzquelle,
+}
+
+// Die laut DBA auf Dividenden an in Österreich ansässige Privatanleger höchstens anrechenbaren
+// Quellensteuersätze in Prozent, je Land ab dem angegebenen Jahr.
+// Alles ohne Gewähr, Abweichungen können je Wertpapier angegeben werden.
+const DBA_SÄTZE: &[(&str, i32, i64)] = &[
+    ("AU", 2000, 15),
+    ("BE", 2000, 15),
+    ("CA", 2000, 15),
+    ("CH", 2000, 15),
+    ("CN", 2000, 10),
+    ("DE", 2000, 15),
+    ("DK", 2000, 15),
+    ("ES", 2000, 15),
+    ("FR", 2000, 15),
+    ("GB", 2000, 15),
+    ("IE", 2000, 15),
+    ("IN", 2000, 10),
+    ("IT", 2000, 15),
+    // Das neue DBA mit Japan gilt ab 2019.
+    ("JP", 2000, 20),
+    ("JP", 2019, 10),
+    ("KR", 2000, 15),
+    ("LU", 2000, 15),
+    ("NL", 2000, 15),
+    ("NO", 2000, 15),
+    ("PT", 2000, 15),
+    ("SE", 2000, 10),
+    ("US", 2000, 15),
+];
+
+const PAUSCHALER_SATZ: i64 = 15;
+
+/// Der im Jahr gültige Quellensteuersatz laut DBA, falls das Land in der Tabelle ist.
+pub fn dba_satz(land: Land, jahr: i32) -> Option<Zahl> {
+    DBA_SÄTZE
+        .iter()
+        .rev()
+        .find(|(code, ab, _)| *code == land.code() && *ab <= jahr)
+        .map(|(_, _, prozent)| Zahl::new(*prozent, 100))
+}
+
+pub fn quellensteuersatz(land: Land, angegeben: Option<Zahl>, jahr: i32) -> Quellensteuersatz {
+    let (satz, quelle) = if land == Land::ÖSTERREICH {
+        (Zahl::new(275, 1000), Satzquelle::Inland)
+    } else if let Some(prozent) = angegeben {
+        (prozent / 100, Satzquelle::Angegeben)
+    } else if let Some(satz) = dba_satz(land, jahr) {
+        (satz, Satzquelle::Dba)
+    } else {
+        (Zahl::new(PAUSCHALER_SATZ, 100), Satzquelle::Pauschal)
+    };
+    Quellensteuersatz { land, satz, quelle }
+}
+
+// Fristen für die Rückforderung von Quellensteuer in Jahren ab dem Ende des Jahres der
+// Dividendenzahlung, ebenfalls ohne Gewähr.
+const RÜCKFORDERUNGSFRISTEN: &[(&str, i32)] = &[
+    ("CH", 3),
+    ("DE", 4),
+    ("DK", 3),
+    ("ES", 4),
+    ("FR", 2),
+    ("IT", 4),
+    ("NL", 3),
+];
+
+const ÜBLICHE_FRIST: i32 = 3;
+
+/// Der letzte Tag, an dem die Quellensteuer auf Dividenden des Jahres zurückgefordert werden kann.
+pub fn rückforderungsfrist(land: Land, jahr: i32) -> Datum {
+    let frist = RÜCKFORDERUNGSFRISTEN
+        .iter()
+        .find(|(code, _)| *code == land.code())
+        .map_or(ÜBLICHE_FRIST, |(_, frist)| *frist);
+    Datum::from_ymd_opt(jahr + frist, 12, 31).unwrap()
+}
+
+/// Die rückforderbare Quellensteuer aus den Dividenden eines Landes und Jahres.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rückforderung {
+    /// Die über den Satz laut DBA hinaus einbehaltene Quellensteuer
+    pub rückforderbar: Zahl,
+    pub rückerstattet: Zahl,
+}
+
+impl Rückforderung {
+    pub fn offen(&self) -> Zahl {
+        (self.rückforderbar - self.rückerstattet).max(Zahl::zero())
+    }
+}
+
+pub type Rückforderungen = BTreeMap<(Land, i32), Rückforderung>;
+
+/// Erfasst die rückforderbare Quellensteuer aus den Dividenden eines Wertpapiers. Rückerstattungen
+/// werden den Dividenden des angegebenen Jahres zugerechnet, ein allfälliger Währungsunterschied
+/// zur ursprünglich einbehaltenen Quellensteuer bleibt unberücksichtigt.
+pub fn rückforderungen_erfassen<'a>(
+    rückforderungen: &mut Rückforderungen,
+    transaktionen: impl IntoIterator<Item = &'a Transaktion>,
+) {
+    for transaktion in transaktionen {
+        match (&transaktion.typ, &transaktion.steuer) {
+            (
+                TransaktionsTyp::Dividende {
+                    quellensteuersatz, ..
+                }
+                | TransaktionsTyp::Aktiendividende {
+                    quellensteuersatz, ..
+                },
+                Steuer::Dividende(steuer),
+            ) if !steuer.nicht_anrechenbare_quellensteuer.is_zero() => {
+                let schlüssel = (quellensteuersatz.land, transaktion.datum.year());
+                let betrag = steuer.nicht_anrechenbare_quellensteuer;
+                rückforderungen.entry(schlüssel).or_default().rückforderbar += betrag;
+            }
+            (TransaktionsTyp::Rückerstattung { betrag, land, jahr }, _) => {
+                let schlüssel = (*land, *jahr);
+                rückforderungen.entry(schlüssel).or_default().rückerstattet += betrag;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steuern::{dividende_berechnen, rückerstattung_berechnen};
+
+    #[test]
+    fn satz_je_land_und_jahr() {
+        let us = Land::bestimmen("US0378331005", None).unwrap();
+        assert_eq!(dba_satz(us, 2023), Some(Zahl::new(15, 100)));
+
+        let jp = Land::new("jp").unwrap();
+        assert_eq!(dba_satz(jp, 2018), Some(Zahl::new(20, 100)));
+        assert_eq!(dba_satz(jp, 2019), Some(Zahl::new(10, 100)));
+
+        // Ein ADR mit US-ISIN eines Unternehmens mit Sitz in Taiwan
+        let tw = Land::bestimmen("US8740391003", Some("TW")).unwrap();
+        let satz = quellensteuersatz(tw, None, 2023);
+        assert_eq!(satz.quelle, Satzquelle::Pauschal);
+        let satz = quellensteuersatz(tw, Some(Zahl::from(21)), 2023);
+        assert_eq!(satz.quelle, Satzquelle::Angegeben);
+        assert_eq!(satz.satz, Zahl::new(21, 100));
+
+        assert!(Land::new("X").is_err());
+    }
+
+    #[test]
+    fn rückforderung_schweizer_dividenden() {
+        let ch = Land::new("CH").unwrap();
+        let transaktion = |datum: &str, (bestand, typ, steuer)| Transaktion {
+            datum: datum.parse().unwrap(),
+            bestand,
+            typ,
+            steuer,
+            fremdwährung: None,
+        };
+        let dividende = |jahr| {
+            let satz = quellensteuersatz(ch, None, jahr);
+            dividende_berechnen(Default::default(), satz, 100.into(), 65.into())
+        };
+        let transaktionen = [
+            transaktion("2022-05-01", dividende(2022)),
+            transaktion("2023-05-01", dividende(2023)),
+            transaktion(
+                "2023-09-01",
+                rückerstattung_berechnen(Default::default(), 15.into(), ch, 2023),
+            ),
+        ];
+
+        let mut rückforderungen = Rückforderungen::new();
+        rückforderungen_erfassen(&mut rückforderungen, &transaktionen);
+        // die Rückerstattung gilt für das angegebene Jahr, auch wenn 2022 noch offen ist
+        let r2022 = rückforderungen[&(ch, 2022)];
+        assert_eq!(r2022.rückforderbar, Zahl::from(20));
+        assert_eq!(r2022.offen(), Zahl::from(20));
+        let r2023 = rückforderungen[&(ch, 2023)];
+        assert_eq!(r2023.rückerstattet, Zahl::from(15));
+        assert_eq!(r2023.offen(), Zahl::from(5));
+
+        let frist = rückforderungsfrist(ch, 2023);
+        assert_eq!(frist, "2026-12-31".parse().unwrap());
+    }
+}
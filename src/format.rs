@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt;
 
 use anyhow::{Context, Result};
@@ -7,12 +8,15 @@ pub use num_rational::Rational64;
 use serde::Deserialize;
 pub use smol_str::SmolStr as String;
 
-#[derive(Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum WertpapierTyp {
     Aktie,
     Etf,
     Fond,
+    Anleihe,
+    Krypto,
+    Derivat,
 }
 
 impl fmt::Display for WertpapierTyp {
@@ -21,6 +25,25 @@ impl fmt::Display for WertpapierTyp {
             WertpapierTyp::Aktie => "Aktie",
             WertpapierTyp::Etf => "ETF",
             WertpapierTyp::Fond => "(schwarzer) Fond",
+            WertpapierTyp::Anleihe => "Anleihe",
+            WertpapierTyp::Krypto => "Kryptowährung",
+            WertpapierTyp::Derivat => "Derivat",
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Optionsart {
+    Call,
+    Put,
+}
+
+impl fmt::Display for Optionsart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Optionsart::Call => "Call",
+            Optionsart::Put => "Put",
         })
     }
 }
@@ -31,35 +54,200 @@ pub struct Wertpapier {
     pub name: String,
     pub isin: String,
     pub symbol: Option<String>,
+    /// Das Depot, kann auch über eine `depot.yaml` im Verzeichnis angegeben werden.
+    #[serde(default)]
+    pub depot: Option<String>,
+    /// Das Sitzland des Emittenten, falls es nicht dem Präfix der ISIN entspricht, etwa bei ADRs.
+    #[serde(default)]
+    pub land: Option<String>,
+    /// Der anrechenbare Quellensteuersatz für Dividenden in Prozent, statt jenem laut DBA.
+    #[serde(default)]
+    pub quellensteuersatz: Option<Zahl>,
     pub transaktionen: Vec<Transaktion>,
 }
 
+impl Wertpapier {
+    /// Führt die Transaktionen eines weiteren Wertpapiers mit gleicher ISIN im gleichen Depot
+    /// zusammen, da laut § 27a (4) 3. der gleitende Durchschnittspreis je Depot gilt.
+    pub fn zusammenführen(&mut self, andere: Wertpapier) -> Result<()> {
+        anyhow::ensure!(
+            self.typ == andere.typ,
+            "`{}` ist sowohl als {} als auch als {} angegeben",
+            self.isin,
+            self.typ,
+            andere.typ
+        );
+        if self.symbol.is_none() {
+            self.symbol = andere.symbol;
+        }
+        if self.land.is_none() {
+            self.land = andere.land;
+        }
+        if self.quellensteuersatz.is_none() {
+            self.quellensteuersatz = andere.quellensteuersatz;
+        }
+        self.transaktionen.extend(andere.transaktionen);
+        Ok(())
+    }
+
+    /// Alle Depots und ISINs, in die dieses Wertpapier Bestand überträgt.
+    pub fn ziele(&self) -> impl Iterator<Item = (Option<String>, String)> + '_ {
+        self.transaktionen.iter().filter_map(|t| match t {
+            Transaktion::Depotausgang(_, _, depot) => {
+                Some((Some(depot.clone()), self.isin.clone()))
+            }
+            Transaktion::Umtausch(_, _, isin, _, _)
+            | Transaktion::Ausgliederung(_, _, isin, ..)
+            | Transaktion::Ausübung(_, _, _, isin, _) => Some((self.depot.clone(), isin.clone())),
+            _ => None,
+        })
+    }
+}
+
+/// Die Metadaten eines Depots, aus einer `depot.yaml`, welche für alle Wertpapiere
+/// im gleichen Verzeichnis und allen Unterverzeichnissen gilt.
+#[derive(Debug, Deserialize)]
+pub struct Depot {
+    pub name: String,
+    /// Ein inländisches Depot, dessen Einkünfte bereits dem KeSt-Abzug unterliegen und unter
+    /// eigenen Kennzahlen erklärt werden.
+    #[serde(default)]
+    pub inland: bool,
+    /// Die laut Bescheinigung der Bank je Jahr einbehaltene KeSt eines inländischen Depots,
+    /// nach dem Verlustausgleich der Bank und abzüglich der angerechneten Quellensteuer.
+    #[serde(default)]
+    pub kest: BTreeMap<i32, Zahl>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Transaktion {
-    Kauf(Datum, Zahl, Zahl, #[serde(default)] Option<Zahl>),
-    Verkauf(Datum, Zahl, Zahl, #[serde(default)] Option<Zahl>),
+    /// Kauf mit Stück, Preis, Spesen und bei Anleihen den gezahlten Stückzinsen.
+    /// Bei Anleihen sind die Stück der Nominalbetrag, und der Preis der Kurs in Prozent.
+    Kauf(
+        Datum,
+        Zahl,
+        Betrag,
+        #[serde(default)] Option<Betrag>,
+        #[serde(default)] Option<Betrag>,
+    ),
+    /// Verkauf mit Stück, Preis, Spesen und bei Anleihen den erhaltenen Stückzinsen
+    Verkauf(
+        Datum,
+        Zahl,
+        Betrag,
+        #[serde(default)] Option<Betrag>,
+        #[serde(default)] Option<Betrag>,
+    ),
 
     Split(Datum, Zahl),
-    Ausgliederung(Datum, Zahl, String),
-    Einbuchung(Datum, Zahl),
-    Spitzenverwertung(Datum, Zahl, Zahl),
+    /// Verschmelzung eines Fonds, mit der ISIN des übernehmenden Fonds und dem Umtauschverhältnis,
+    /// optional mit dem Symbol für die Rücknahmepreise des übernehmenden Fonds
+    Fondsverschmelzung(Datum, String, Zahl, #[serde(default)] Option<String>),
+    /// Kapitalerhöhung aus Gesellschaftsmitteln, mit der Anzahl der erhaltenen Gratisaktien
+    Gratisaktien(Datum, Zahl),
+    /// Dividende in Form von Aktien, mit dem Wert je Stück und einer einbehaltenen Quellensteuer
+    Aktiendividende(Datum, Zahl, Betrag, #[serde(default)] Option<Betrag>),
+    /// Ausgliederung mit Faktor und ISIN der neuen Aktie, optional mit den Verkehrswerten
+    /// je Stück der bestehenden und der neuen Aktie
+    Ausgliederung(
+        Datum,
+        Zahl,
+        String,
+        #[serde(default)] Option<Betrag>,
+        #[serde(default)] Option<Betrag>,
+    ),
+    /// Einbuchung von Stück, optional mit dem Verkehrswert je Stück
+    Einbuchung(Datum, Zahl, #[serde(default)] Option<Betrag>),
+    Spitzenverwertung(Datum, Zahl, Betrag),
+    /// Ausbuchung des gesamten Bestandes, etwa nach Insolvenz oder einem Squeeze-out,
+    /// mit einer optionalen Abfindung je Stück
+    Ausbuchung(Datum, #[serde(default)] Option<Betrag>),
+
+    /// Tilgung einer Anleihe mit dem Nominalbetrag, optional mit dem Tilgungskurs in Prozent
+    Tilgung(Datum, Zahl, #[serde(default)] Option<Betrag>),
 
-    Dividende(Datum, Zahl, Zahl),
+    Dividende(Datum, Betrag, Betrag),
+    /// Vom Quellenstaat rückerstattete, nicht anrechenbare Quellensteuer, mit dem Jahr der
+    /// Dividenden, für die diese zurückgefordert wurde
+    Rückerstattung(Datum, Betrag, i32),
+    /// Zinszahlung einer Anleihe
+    Kupon(Datum, Betrag),
+    /// Erhaltene Kryptowährung aus Staking, mit dem Wert je Stück
+    Staking(Datum, Zahl, Betrag),
+
+    /// Verkauf einer Option als Stillhalter, mit der erhaltenen Prämie je Stück. Optionen werden
+    /// in Stück des Basiswertes angegeben, nicht in Kontrakten.
+    Stillhalter(Datum, Zahl, Betrag),
+    /// Rückkauf einer als Stillhalter verkauften Option, mit dem Preis je Stück
+    Glattstellung(Datum, Zahl, Betrag),
+    /// Wertloser Verfall des gesamten Bestandes einer Option
+    Verfall(Datum),
+    /// Ausübung oder Andienung einer Option, mit der Art der Option, der ISIN des Basiswertes
+    /// und dem Basispreis. Die Stück sind jene des Basiswertes.
+    Ausübung(Datum, Zahl, Optionsart, String, Betrag),
+    /// Einlagenrückzahlung mit dem gesamten ausgezahlten Betrag
+    Einlagenrückzahlung(Datum, Betrag),
     Ausschüttung(Datum, Zahl),
+
+    /// Übertrag von Stück in ein anderes Depot, mit Name des Zieldepots
+    Depotausgang(Datum, Zahl, String),
+    /// Übernahme von Stück aus einem anderen Depot, mit Name des Quelldepots
+    Depoteingang(Datum, Zahl, String),
+
+    /// Umtausch von Stück in ein anderes Wertpapier, etwa bei einer Verschmelzung, mit der
+    /// ISIN des neuen Wertpapiers, den dafür erhaltenen Stück und einer baren Zuzahlung
+    Umtausch(Datum, Zahl, String, Zahl, #[serde(default)] Option<Betrag>),
+    /// Einbuchung der bei einem Umtausch erhaltenen Stück, mit der ISIN des alten Wertpapiers
+    Umtauscheingang(Datum, Zahl, String),
+
+    /// Erwerb durch Schenkung oder Erbschaft, mit den Anschaffungskosten und dem
+    /// Anschaffungsdatum des Rechtsvorgängers
+    Unentgeltlich(Datum, Zahl, Betrag, Datum),
+    /// Unentgeltliche Übertragung an einen anderen Steuerpflichtigen
+    Schenkung(Datum, Zahl),
+
+    /// Einbuchung von Bezugsrechten
+    Bezugsrecht(Datum, Zahl),
+    /// Verkauf von Bezugsrechten, mit Preis je Bezugsrecht
+    Bezugsrechtverkauf(Datum, Zahl, Betrag),
+    /// Ausübung von Bezugsrechten, mit den bezogenen Stück und dem Bezugspreis je Stück
+    Bezugsrechtausübung(Datum, Zahl, Zahl, Betrag),
 }
 
 impl Transaktion {
     pub fn datum(&self) -> Datum {
         match self {
-            Transaktion::Kauf(datum, _, _, _) => *datum,
-            Transaktion::Verkauf(datum, _, _, _) => *datum,
+            Transaktion::Kauf(datum, ..) => *datum,
+            Transaktion::Verkauf(datum, ..) => *datum,
+            Transaktion::Tilgung(datum, _, _) => *datum,
+            Transaktion::Kupon(datum, _) => *datum,
+            Transaktion::Staking(datum, _, _) => *datum,
+            Transaktion::Stillhalter(datum, _, _) => *datum,
+            Transaktion::Glattstellung(datum, _, _) => *datum,
+            Transaktion::Verfall(datum) => *datum,
+            Transaktion::Ausübung(datum, ..) => *datum,
             Transaktion::Spitzenverwertung(datum, _, _) => *datum,
-            Transaktion::Ausgliederung(datum, _, _) => *datum,
-            Transaktion::Einbuchung(datum, _) => *datum,
+            Transaktion::Ausgliederung(datum, ..) => *datum,
+            Transaktion::Einbuchung(datum, ..) => *datum,
             Transaktion::Split(datum, _) => *datum,
+            Transaktion::Fondsverschmelzung(datum, ..) => *datum,
+            Transaktion::Ausbuchung(datum, _) => *datum,
+            Transaktion::Einlagenrückzahlung(datum, _) => *datum,
+            Transaktion::Gratisaktien(datum, _) => *datum,
+            Transaktion::Aktiendividende(datum, ..) => *datum,
             Transaktion::Dividende(datum, _, _) => *datum,
+            Transaktion::Rückerstattung(datum, ..) => *datum,
             Transaktion::Ausschüttung(datum, _) => *datum,
+            Transaktion::Depotausgang(datum, _, _) => *datum,
+            Transaktion::Depoteingang(datum, _, _) => *datum,
+            Transaktion::Umtausch(datum, ..) => *datum,
+            Transaktion::Umtauscheingang(datum, _, _) => *datum,
+            Transaktion::Unentgeltlich(datum, _, _, _) => *datum,
+            Transaktion::Schenkung(datum, _) => *datum,
+            Transaktion::Bezugsrecht(datum, _) => *datum,
+            Transaktion::Bezugsrechtverkauf(datum, _, _) => *datum,
+            Transaktion::Bezugsrechtausübung(datum, _, _, _) => *datum,
         }
     }
 }
@@ -85,6 +273,40 @@ impl TryFrom<Cow<'_, str>> for Zahl {
     }
 }
 
+/// Ein Geldbetrag, optional mit einer Währung, wie etwa `12.5 USD`.
+/// Ohne Angabe einer Währung wird EUR angenommen.
+#[derive(Debug, Default, Deserialize)]
+#[serde(try_from = "Cow<'_, str>")]
+pub struct Betrag {
+    pub wert: Rational64,
+    pub währung: Option<String>,
+}
+
+impl TryFrom<Cow<'_, str>> for Betrag {
+    type Error = anyhow::Error;
+
+    fn try_from(s: Cow<'_, str>) -> Result<Self> {
+        let mut split = s.split_whitespace();
+        let wert = split.next().context("Betrag erwartet")?;
+        let Zahl(wert) = Zahl::try_from(Cow::Borrowed(wert))?;
+
+        let währung = match split.next() {
+            Some(währung) => {
+                anyhow::ensure!(
+                    währung.len() == 3 && währung.chars().all(|c| c.is_ascii_alphabetic()),
+                    "Ungültige Währung `{währung}`"
+                );
+                let währung = währung.to_ascii_uppercase();
+                (währung != "EUR").then(|| währung.into())
+            }
+            None => None,
+        };
+        anyhow::ensure!(split.next().is_none(), "Ungültiger Betrag `{s}`");
+
+        Ok(Betrag { wert, währung })
+    }
+}
+
 fn parse_kommazahl(s: &str) -> Result<Rational64> {
     let mut split = s.trim().splitn(2, '.');
     let vor = split.next().context("Zahl erwartet")?;
@@ -121,8 +343,67 @@ transaktionen:
 - split: [2023-02-02, 1/3]
 - split: [2023-03-03, 3]
 - verkauf: [2023-04-04, 40, 32]
+- kauf: [2023-05-05, 10, 30.5 USD, 1.5]
+- dividende: [2023-06-06, 10 usd, 8.5 USD]
         "#;
         let wertpapier: Wertpapier = serde_yaml::from_str(contents).unwrap();
         dbg!(wertpapier);
     }
+
+    #[test]
+    fn zusammenführen() {
+        let mut wertpapier: Wertpapier = serde_yaml::from_str(
+            r#"
+typ: aktie
+name: Foo
+isin: US000
+depot: A
+transaktionen:
+- kauf: [2023-01-01, 10, 100]
+        "#,
+        )
+        .unwrap();
+        let andere: Wertpapier = serde_yaml::from_str(
+            r#"
+typ: aktie
+name: Foo Inc.
+isin: US000
+symbol: FOO
+depot: A
+transaktionen:
+- verkauf: [2023-02-01, 5, 110]
+- dividende: [2023-03-01, 10, 8.5]
+        "#,
+        )
+        .unwrap();
+
+        wertpapier.zusammenführen(andere).unwrap();
+        assert_eq!(wertpapier.name, "Foo");
+        assert_eq!(wertpapier.symbol.as_deref(), Some("FOO"));
+        assert_eq!(wertpapier.transaktionen.len(), 3);
+
+        let etf: Wertpapier = serde_yaml::from_str(
+            r#"
+typ: etf
+name: Foo
+isin: US000
+transaktionen: []
+        "#,
+        )
+        .unwrap();
+        assert!(wertpapier.zusammenführen(etf).is_err());
+    }
+
+    #[test]
+    fn betrag() {
+        let betrag = Betrag::try_from(Cow::Borrowed("12.5 usd")).unwrap();
+        assert_eq!(betrag.wert, Rational64::new(25, 2));
+        assert_eq!(betrag.währung.as_deref(), Some("USD"));
+
+        let betrag = Betrag::try_from(Cow::Borrowed("3/4 EUR")).unwrap();
+        assert_eq!(betrag.wert, Rational64::new(3, 4));
+        assert_eq!(betrag.währung, None);
+
+        assert!(Betrag::try_from(Cow::Borrowed("12 Dollar")).is_err());
+    }
 }
@@ -30,11 +30,51 @@ impl fmt::Display for Stück {
     }
 }
 
+/// Stück einer Kryptowährung, mit Bruchteilen als Kommazahl, sofern diese endlich ist.
+pub struct KryptoStück(pub Zahl);
+impl fmt::Display for KryptoStück {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match nachkommastellen(*self.0.denom()) {
+            Some(stellen) if stellen > 0 => write!(f, "{}", Dezimal(self.0, stellen)),
+            _ => write!(f, "{}", Stück(self.0)),
+        }
+    }
+}
+
+/// Die Anzahl der Nachkommastellen eines Bruches mit diesem Nenner, falls dieser
+/// als Kommazahl mit höchstens 12 Stellen darstellbar ist.
+fn nachkommastellen(mut nenner: i64) -> Option<u32> {
+    let mut zwei = 0;
+    while nenner % 2 == 0 {
+        nenner /= 2;
+        zwei += 1;
+    }
+    let mut fünf = 0;
+    while nenner % 5 == 0 {
+        nenner /= 5;
+        fünf += 1;
+    }
+    let stellen = u32::max(zwei, fünf);
+    (nenner == 1 && stellen <= 12).then_some(stellen)
+}
+
 pub struct Eur(pub Zahl, pub u32);
 impl fmt::Display for Eur {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("€ ")?;
+        write!(f, "€ {}", Dezimal(self.0, self.1))
+    }
+}
 
+pub struct Währung<'a>(pub Zahl, pub &'a str, pub u32);
+impl fmt::Display for Währung<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.1, Dezimal(self.0, self.2))
+    }
+}
+
+pub struct Dezimal(pub Zahl, pub u32);
+impl fmt::Display for Dezimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let faktor = 10_i64.pow(self.1);
         let zahl = (self.0 * faktor).round().to_integer();
 
@@ -59,3 +99,25 @@ static DECIMAL_FORMATTER: Lazy<FixedDecimalFormatter> = Lazy::new(|| {
     )
     .unwrap()
 });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stück_mit_bruchteilen() {
+        assert_eq!(Stück(Zahl::from(12)).to_string(), "12");
+        assert_eq!(Stück(Zahl::new(1, 3)).to_string(), "1/3");
+        assert_eq!(Stück(Zahl::new(4, 3)).to_string(), "1 1/3");
+        assert_eq!(Stück(Zahl::new(5, 2)).to_string(), "2 1/2");
+
+        // nur bei Kryptowährungen als Kommazahl
+        assert_eq!(KryptoStück(Zahl::from(12)).to_string(), "12");
+        assert_eq!(KryptoStück(Zahl::new(4, 3)).to_string(), "1 1/3");
+        assert_eq!(
+            KryptoStück(Zahl::new(123_456, 100_000_000)).to_string(),
+            "0,00123456"
+        );
+        assert_eq!(KryptoStück(Zahl::new(5, 2)).to_string(), "2,5");
+    }
+}
@@ -2,6 +2,7 @@ extern crate alloc;
 
 pub mod berechnung;
 pub mod cacher;
+pub mod dba;
 pub mod format;
 pub mod formatierung;
 pub mod kursdaten;
@@ -11,5 +12,6 @@ pub mod steuern;
 pub mod tsv;
 pub mod typen;
 pub mod waehrungen;
+pub mod xml;
 
 pub use typen::*;
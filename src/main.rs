@@ -1,15 +1,17 @@
-use std::collections::HashMap;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use globset::GlobBuilder;
+use serde::de::DeserializeOwned;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use walkdir::WalkDir;
 
-use berechnung::Rechner;
+use berechnung::{reihenfolge, Rechner};
 use fondoeh::*;
 use report::BREITE;
 
@@ -26,6 +28,23 @@ struct Args {
     /// Für welches Jahr die Berechnung erfolgen soll
     #[arg(short, long)]
     jahr: Option<i32>,
+
+    /// Ausgabe und Zusammenfassung nach Depots gruppieren
+    #[arg(short = 'd', long)]
+    nach_depot: bool,
+
+    /// Die Zusammenfassung des Jahres als XML im Aufbau der E1kv ausgeben, ungeprüft und nicht
+    /// zum Hochladen in FinanzOnline
+    #[arg(short, long, requires_all = ["jahr", "steuernummer"], conflicts_with = "tsv")]
+    xml: bool,
+
+    /// Die Steuernummer für die XML-Ausgabe, etwa `12-345/6789`
+    #[arg(short, long)]
+    steuernummer: Option<String>,
+
+    /// Nur die rückforderbare Quellensteuer je Land und Jahr ausgeben
+    #[arg(short, long = "rückforderungen")]
+    rückforderungen: bool,
 }
 
 #[tokio::main]
@@ -38,24 +57,90 @@ async fn main() -> anyhow::Result<()> {
         .init();
     // tracing_subscriber::fmt().init();
 
-    let gefundene_daten = finde_alle_daten(args.daten)?;
+    let (gefundene_daten, inländische_depots) = finde_alle_daten(args.daten)?;
 
     let mut rechner = Rechner::new().await?;
 
+    let gefundene_daten = reihenfolge(gefundene_daten.into_values().collect())?;
+
     let mut wertpapiere = Vec::with_capacity(gefundene_daten.len());
-    for (pfad, wertpapier) in gefundene_daten {
-        let context = format!("Auswertung von `{}` ({})", wertpapier.name, pfad.display());
+    for (pfade, wertpapier) in gefundene_daten {
+        let pfade: Vec<_> = pfade.iter().map(|p| p.display().to_string()).collect();
+        let context = format!(
+            "Auswertung von `{}` ({})",
+            wertpapier.name,
+            pfade.join(", ")
+        );
         let wertpapier = rechner
             .wertpapier_auswerten(wertpapier)
             .await
             .context(context)?;
         wertpapiere.push(wertpapier);
     }
+    for wertpapier in rechner.ausgegliederte_wertpapiere() {
+        let context = format!("Auswertung der Ausgliederung in `{}`", wertpapier.isin);
+        let wertpapier = rechner
+            .wertpapier_auswerten(wertpapier)
+            .await
+            .context(context)?;
+        wertpapiere.push(wertpapier);
+    }
+    rechner.offene_überträge_prüfen()?;
 
-    wertpapiere.sort_by(|a, b| (&a.typ, &a.name).cmp(&(&b.typ, &b.name)));
+    if args.nach_depot {
+        wertpapiere.sort_by(|a, b| (&a.depot, &a.typ, &a.name).cmp(&(&b.depot, &b.typ, &b.name)));
+    } else {
+        wertpapiere.sort_by(|a, b| (&a.typ, &a.name, &a.depot).cmp(&(&b.typ, &b.name, &b.depot)));
+    }
     // dbg!(&wertpapiere);
 
     let mut w = std::io::stdout().lock();
+    if let (true, Some(jahr), Some(steuernummer)) = (args.xml, args.jahr, &args.steuernummer) {
+        let steuernummer = xml::steuernummer_prüfen(steuernummer)?;
+        let mut summe = SteuerJahr::new(jahr);
+        let mut mit_einkünften = BTreeSet::new();
+        for wertpapier in &wertpapiere {
+            let inland = inländisches_depot(&inländische_depots, wertpapier);
+            for t in wertpapier
+                .iter_jahre(Some(jahr))
+                .flat_map(|j| &j.transaktionen)
+            {
+                summe.hinzufügen(t.steuer, inland.is_some());
+                if let (Some(depot), false) = (inland, matches!(t.steuer, Steuer::Keine)) {
+                    mit_einkünften.insert(depot);
+                }
+            }
+        }
+        for depot in mit_einkünften {
+            kest_anrechnen(&mut summe, depot, &inländische_depots[depot])?;
+        }
+        xml::kennzahlen_prüfen(&summe)?;
+        let erklärung = xml::XmlErklärung {
+            summe: &summe,
+            steuernummer: &steuernummer,
+            erstellt: rechner.heute,
+        };
+        write!(w, "{erklärung}")?;
+        return Ok(());
+    }
+    if args.rückforderungen {
+        let mut rückforderungen = dba::Rückforderungen::new();
+        for wertpapier in &wertpapiere {
+            let transaktionen = wertpapier.jahre.iter().flat_map(|j| &j.transaktionen);
+            dba::rückforderungen_erfassen(&mut rückforderungen, transaktionen);
+        }
+        if let Some(jahr) = args.jahr {
+            rückforderungen.retain(|&(_, j), _| j == jahr);
+        }
+        let heute = rechner.heute;
+        let report = report::ReportRückforderungen {
+            rückforderungen: &rückforderungen,
+            heute,
+        };
+        write!(w, "{report}")?;
+        return Ok(());
+    }
+
     if args.tsv {
         write!(w, "{}", tsv::TsvTitel)?;
     }
@@ -68,12 +153,39 @@ async fn main() -> anyhow::Result<()> {
         None
     };
 
+    let mut summe_depot = summe_steuer;
+    let mut aktuelles_depot = None;
+    let mut mit_einkünften = BTreeSet::new();
+
     while let Some(wertpapier) = wertpapiere.next() {
         let mut jahre = wertpapier.iter_jahre(args.jahr).peekable();
         if jahre.peek().is_none() {
             continue;
         };
 
+        if args.nach_depot && !args.tsv && aktuelles_depot != Some(&wertpapier.depot) {
+            if let (Some(depot), Some(summe)) = (aktuelles_depot, &mut summe_depot) {
+                if let Some(depot) = depot.as_ref().filter(|d| mit_einkünften.contains(d)) {
+                    kest_anrechnen(summe, depot, &inländische_depots[depot])?;
+                }
+                let depot = Some(depot.as_deref());
+                writeln!(
+                    w,
+                    "{}",
+                    report::SteuerSumme {
+                        summe: *summe,
+                        depot
+                    }
+                )?;
+                writeln!(w, "{:#<BREITE$}", "")?;
+                writeln!(w)?;
+                *summe = SteuerJahr::new(summe.jahr);
+            }
+            aktuelles_depot = Some(&wertpapier.depot);
+            let depot = wertpapier.depot.as_deref();
+            write!(w, "{}", report::ReportDepot { depot })?;
+        }
+
         if !args.tsv {
             write!(w, "{}", report::ReportTitel { wertpapier })?;
         }
@@ -83,13 +195,25 @@ async fn main() -> anyhow::Result<()> {
             if args.tsv {
                 write!(w, "{}", tsv::TsvWertpapier { wertpapier, jahr })?;
             } else {
-                write!(w, "{}", report::ReportJahr { jahr })?;
+                write!(
+                    w,
+                    "{}",
+                    report::ReportJahr {
+                        typ: wertpapier.typ,
+                        jahr
+                    }
+                )?;
             }
             letztes_jahr = Some(jahr);
 
-            if let Some(summe) = &mut summe_steuer {
+            if let (Some(summe), Some(summe_depot)) = (&mut summe_steuer, &mut summe_depot) {
+                let inland = inländisches_depot(&inländische_depots, wertpapier);
                 for t in &jahr.transaktionen {
-                    *summe += t.steuer;
+                    summe.hinzufügen(t.steuer, inland.is_some());
+                    summe_depot.hinzufügen(t.steuer, inland.is_some());
+                    if let (Some(depot), false) = (inland, matches!(t.steuer, Steuer::Keine)) {
+                        mit_einkünften.insert(depot);
+                    }
                 }
             }
         }
@@ -97,6 +221,7 @@ async fn main() -> anyhow::Result<()> {
             let letztes_jahr = letztes_jahr.unwrap();
             let datum = letztes_jahr.letzter().min(rechner.heute);
             let bestand = report::ReportBestandAm {
+                typ: wertpapier.typ,
                 datum,
                 bestand: letztes_jahr.bestand_ende,
             };
@@ -110,38 +235,180 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    if let Some(summe) = summe_steuer {
-        writeln!(w, "{}", report::SteuerSumme { summe })?;
+    if let (Some(depot), Some(mut summe)) = (aktuelles_depot, summe_depot) {
+        if let Some(depot) = depot.as_ref().filter(|d| mit_einkünften.contains(d)) {
+            kest_anrechnen(&mut summe, depot, &inländische_depots[depot])?;
+        }
+        let depot = Some(depot.as_deref());
+        writeln!(w, "{}", report::SteuerSumme { summe, depot })?;
+        writeln!(w, "{:#<BREITE$}", "")?;
+        writeln!(w)?;
+    }
+    if let Some(mut summe) = summe_steuer {
+        for depot in mit_einkünften {
+            kest_anrechnen(&mut summe, depot, &inländische_depots[depot])?;
+        }
+        writeln!(w, "{}", report::SteuerSumme { summe, depot: None })?;
     }
 
     Ok(())
 }
 
-fn finde_alle_daten(daten: Vec<PathBuf>) -> Result<HashMap<PathBuf, format::Wertpapier>> {
-    let mut gefundene_daten = HashMap::new();
+type Daten = BTreeMap<(Option<String>, String), (Vec<PathBuf>, format::Wertpapier)>;
+
+/// Die inländischen Depots, mit der je Jahr von der Bank einbehaltenen KeSt.
+type InländischeDepots = HashMap<String, BTreeMap<i32, Zahl>>;
 
+/// Das Depot eines Wertpapiers, falls dieses inländisch ist.
+fn inländisches_depot<'a>(
+    depots: &InländischeDepots,
+    wertpapier: &'a Wertpapier,
+) -> Option<&'a String> {
+    wertpapier
+        .depot
+        .as_ref()
+        .filter(|depot| depots.contains_key(*depot))
+}
+
+/// Rechnet die von der Bank einbehaltene KeSt eines inländischen Depots als 899 an. Diese muss
+/// laut Bescheinigung der Bank angegeben sein, da sie von deren eigenem Verlustausgleich abhängt.
+fn kest_anrechnen(summe: &mut SteuerJahr, depot: &str, kest: &BTreeMap<i32, Zahl>) -> Result<()> {
+    let jahr = summe.jahr;
+    let kest = kest.get(&jahr).with_context(|| {
+        format!(
+            "Für das inländische Depot `{depot}` fehlt die einbehaltene KeSt {jahr}, \
+             bitte laut Bescheinigung der Bank als `kest: {{{jahr}: …}}` in der `depot.yaml` angeben"
+        )
+    })?;
+    summe.gezahlte_inländische_kest_899 += kest;
+    Ok(())
+}
+
+/// Findet alle Wertpapiere, sowie die inländischen Depots.
+fn finde_alle_daten(daten: Vec<PathBuf>) -> Result<(Daten, InländischeDepots)> {
     let glob = GlobBuilder::new("**/*.{yml,yaml}")
         .case_insensitive(true)
         .build()?
         .compile_matcher();
+    let depot_glob = GlobBuilder::new("**/depot.{yml,yaml}")
+        .case_insensitive(true)
+        .build()?
+        .compile_matcher();
 
+    let mut pfade = BTreeSet::new();
     for pfad in daten {
         for entry in WalkDir::new(pfad) {
             let entry = entry?;
-            let pfad = entry.path();
-            if glob.is_match(pfad) {
-                if gefundene_daten.contains_key(pfad) {
-                    continue;
-                }
-                let rdr = fs::File::open(pfad)
-                    .with_context(|| format!("Öffnen von `{}`", pfad.display()))?;
-                let wertpapier = serde_yaml::from_reader(rdr)
-                    .with_context(|| format!("Einlesen von `{}`", pfad.display()))?;
+            if glob.is_match(entry.path()) {
+                pfade.insert(entry.into_path());
+            }
+        }
+    }
+
+    let (depot_pfade, pfade): (Vec<_>, Vec<_>) =
+        pfade.into_iter().partition(|p| depot_glob.is_match(p));
+
+    let mut depots = HashMap::new();
+    let mut inländische_depots = InländischeDepots::new();
+    for pfad in depot_pfade {
+        let depot: format::Depot = lese_yaml(&pfad)?;
+        if depot.inland {
+            let kest = depot.kest.iter().map(|(jahr, kest)| (*jahr, kest.0));
+            inländische_depots.insert(depot.name.clone(), kest.collect());
+        }
+        let verzeichnis = pfad.parent().map(Path::to_path_buf).unwrap_or_default();
+        depots.insert(verzeichnis, depot);
+    }
+
+    let mut gefundene_daten = Daten::new();
+    for pfad in pfade {
+        let mut wertpapier: format::Wertpapier = lese_yaml(&pfad)?;
+        if wertpapier.depot.is_none() {
+            wertpapier.depot = pfad
+                .ancestors()
+                .skip(1)
+                .find_map(|verzeichnis| depots.get(verzeichnis))
+                .map(|depot| depot.name.clone());
+        }
 
-                gefundene_daten.insert(entry.into_path(), wertpapier);
+        let schlüssel = (wertpapier.depot.clone(), wertpapier.isin.clone());
+        match gefundene_daten.entry(schlüssel) {
+            Entry::Vacant(entry) => {
+                entry.insert((vec![pfad], wertpapier));
+            }
+            Entry::Occupied(mut entry) => {
+                let (pfade, vorhandenes) = entry.get_mut();
+                vorhandenes
+                    .zusammenführen(wertpapier)
+                    .with_context(|| format!("Zusammenführen von `{}`", pfad.display()))?;
+                pfade.push(pfad);
             }
         }
     }
 
-    Ok(gefundene_daten)
+    Ok((gefundene_daten, inländische_depots))
+}
+
+fn lese_yaml<T: DeserializeOwned>(pfad: &Path) -> Result<T> {
+    let rdr = fs::File::open(pfad).with_context(|| format!("Öffnen von `{}`", pfad.display()))?;
+    serde_yaml::from_reader(rdr).with_context(|| format!("Einlesen von `{}`", pfad.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depots_aus_verzeichnissen() {
+        let basis = std::env::temp_dir().join(format!("fondoeh-daten-{}", std::process::id()));
+        let datei = |pfad: &str, inhalt: &str| {
+            let pfad = basis.join(pfad);
+            fs::create_dir_all(pfad.parent().unwrap()).unwrap();
+            fs::write(pfad, inhalt).unwrap();
+        };
+        let wertpapier = |kauf: &str| {
+            format!(
+                "typ: aktie\nname: Foo\nisin: US000\ntransaktionen:\n- kauf: [{kauf}, 10, 100]\n"
+            )
+        };
+        datei(
+            "a/depot.yaml",
+            "name: A\ninland: true\nkest:\n  2023: 12.5\n",
+        );
+        datei("a/foo.yaml", &wertpapier("2022-01-01"));
+        datei("a/2023/foo.yaml", &wertpapier("2023-01-01"));
+        datei("b/depot.yml", "name: B\n");
+        datei("b/foo.yaml", &wertpapier("2023-01-01"));
+
+        let ergebnis = finde_alle_daten(vec![basis.clone()]);
+        fs::remove_dir_all(&basis).unwrap();
+        let (daten, inländische_depots) = ergebnis.unwrap();
+
+        // gleiche ISIN im gleichen Depot wird zusammengeführt, in anderen Depots nicht
+        let schlüssel: Vec<_> = daten.keys().cloned().collect();
+        assert_eq!(
+            schlüssel,
+            [
+                (Some("A".into()), "US000".into()),
+                (Some("B".into()), "US000".into())
+            ]
+        );
+        let (pfade, wertpapier) = &daten[&(Some("A".into()), "US000".into())];
+        assert_eq!(pfade.len(), 2);
+        assert_eq!(wertpapier.transaktionen.len(), 2);
+        let kest = BTreeMap::from([(2023, Zahl::new(125, 10))]);
+        assert_eq!(inländische_depots, HashMap::from([("A".into(), kest)]));
+    }
+
+    #[test]
+    fn kest_laut_bescheinigung() {
+        let kest = BTreeMap::from([(2023, Zahl::new(125, 10))]);
+        let mut summe = SteuerJahr::new(2023);
+        kest_anrechnen(&mut summe, "A", &kest).unwrap();
+        assert_eq!(summe.gezahlte_inländische_kest_899, Zahl::new(125, 10));
+
+        let mut summe = SteuerJahr::new(2024);
+        let fehler = kest_anrechnen(&mut summe, "A", &kest).unwrap_err();
+        assert!(fehler.to_string().contains("`kest: {2024: …}`"));
+    }
 }
@@ -3,11 +3,28 @@ use std::fmt::Write;
 
 use num_traits::Zero;
 
-use crate::formatierung::{Eur, Stück};
-use crate::{Bestand, Datum, Jahr, Steuer, SteuerJahr, TransaktionsTyp, Wertpapier, Zahl};
+use crate::dba::{rückforderungsfrist, Rückforderungen, Satzquelle};
+use crate::formatierung::{Dezimal, Eur, KryptoStück, Stück, Währung};
+use crate::{
+    Bestand, Datum, Fremdwährung, Jahr, Kursquelle, Quellensteuersatz, Steuer, SteuerJahr,
+    TransaktionsTyp, Wertpapier, WertpapierTyp, Zahl,
+};
 
 pub const BREITE: usize = 80;
 
+pub struct ReportDepot<'a> {
+    pub depot: Option<&'a str>,
+}
+impl fmt::Display for ReportDepot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Depot: {}", self.depot.unwrap_or(OHNE_DEPOT))?;
+        writeln!(f, "{:#<BREITE$}", "")?;
+        writeln!(f)
+    }
+}
+
+const OHNE_DEPOT: &str = "(ohne Depotangabe)";
+
 pub struct ReportTitel<'a> {
     pub wertpapier: &'a Wertpapier,
 }
@@ -21,20 +38,25 @@ impl fmt::Display for ReportTitel<'_> {
         } else {
             f.write_char('\n')?;
         }
+        if let Some(depot) = &wertpapier.depot {
+            writeln!(f, "Depot: {depot}")?;
+        }
         writeln!(f, "{:=<BREITE$}", "")
     }
 }
 
 pub struct ReportJahr<'a> {
+    pub typ: WertpapierTyp,
     pub jahr: &'a Jahr,
 }
 impl fmt::Display for ReportJahr<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        schreibe_jahr(f, self.jahr)
+        schreibe_jahr(f, self.typ, self.jahr)
     }
 }
 
 pub struct ReportBestandAm {
+    pub typ: WertpapierTyp,
     pub datum: Datum,
     pub bestand: Bestand,
 }
@@ -42,9 +64,11 @@ impl fmt::Display for ReportBestandAm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut w = Writer::new(f);
 
-        w.write_split_fmt(
+        schreibe_bestand(
+            &mut w,
+            self.typ,
             format_args!("{}: Bestand", self.datum),
-            ReportBestand(self.bestand.stück, self.bestand.preis),
+            self.bestand,
         )
         // w.divider('=')
     }
@@ -53,61 +77,294 @@ impl fmt::Display for ReportBestandAm {
 struct ReportBestand(pub Zahl, pub Zahl);
 impl fmt::Display for ReportBestand {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} Stück", Stück(self.0))?;
-        let preis = self.1;
-        if !preis.is_zero() {
-            write!(f, " × {}", Eur(preis, 4))?;
+        schreibe_stück(f, Stück(self.0), self.1)
+    }
+}
+
+fn schreibe_stück(
+    f: &mut fmt::Formatter<'_>,
+    stück: impl fmt::Display,
+    preis: Zahl,
+) -> fmt::Result {
+    write!(f, "{stück} Stück")?;
+    if !preis.is_zero() {
+        write!(f, " × {}", Eur(preis, 4))?;
+    }
+    Ok(())
+}
+
+struct ReportNominale(pub Zahl, pub Zahl);
+impl fmt::Display for ReportNominale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Nominale {}", Dezimal(self.0, 2))?;
+        let kurs = self.1;
+        if !kurs.is_zero() {
+            write!(f, " zu {} %", Dezimal(kurs, 4))?;
         }
         Ok(())
     }
 }
 
-pub fn schreibe_jahr<W: fmt::Write>(w: &mut W, jahr: &Jahr) -> fmt::Result {
+/// Ein Bestand in Stück mit Preis, bei Anleihen als Nominale mit Kurs, da deren Bestand in
+/// Einheiten zu 100 Nominale geführt wird, und bei Kryptowährungen mit Bruchteilen als Kommazahl.
+struct ReportPosten(pub WertpapierTyp, pub Zahl, pub Zahl);
+impl fmt::Display for ReportPosten {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ReportPosten(typ, stück, preis) = *self;
+        match typ {
+            WertpapierTyp::Anleihe => ReportNominale(stück * 100, preis).fmt(f),
+            WertpapierTyp::Krypto => schreibe_stück(f, KryptoStück(stück), preis),
+            _ => ReportBestand(stück, preis).fmt(f),
+        }
+    }
+}
+
+pub fn schreibe_jahr<W: fmt::Write>(w: &mut W, typ: WertpapierTyp, jahr: &Jahr) -> fmt::Result {
     let mut w = Writer::new(w);
 
     let bestand = jahr.bestand_anfang;
-    w.write_split_fmt(
+    schreibe_bestand(
+        &mut w,
+        typ,
         format_args!("{}: Bestand", jahr.erster()),
-        ReportBestand(bestand.stück, bestand.preis),
+        bestand,
     )?;
 
     for transaktion in &jahr.transaktionen {
         let datum = transaktion.datum;
         let bestand = transaktion.bestand;
+        let fremdwährung = transaktion.fremdwährung.as_ref();
 
         w.divider('-')?;
         match &transaktion.typ {
-            TransaktionsTyp::Kauf { stück, preis } => {
-                writeln!(w, "{datum}: Kauf {}", ReportBestand(*stück, *preis))?;
+            TransaktionsTyp::Kauf {
+                stück,
+                preis,
+                spesen,
+            } => {
+                writeln!(w, "{datum}: Kauf {}", ReportPosten(typ, *stück, *preis))?;
+                schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
+                schreibe_spesen(&mut w, *spesen, -transaktion.typ.geldfluss())?;
+            }
+            TransaktionsTyp::Verkauf {
+                stück,
+                preis,
+                spesen,
+            } => {
+                writeln!(w, "{datum}: Verkauf {}", ReportPosten(typ, *stück, *preis))?;
+                schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
+                schreibe_spesen(&mut w, *spesen, transaktion.typ.geldfluss())?;
+            }
+
+            TransaktionsTyp::AnleiheKauf {
+                nominale,
+                kurs,
+                stückzinsen,
+                spesen,
+            } => {
+                writeln!(w, "{datum}: Kauf {}", ReportNominale(*nominale, *kurs))?;
+                schreibe_fremdwährung(&mut w, "Kurs", fremdwährung, 4)?;
+                schreibe_stückzinsen(&mut w, *stückzinsen)?;
+                schreibe_spesen(&mut w, *spesen, -transaktion.typ.geldfluss())?;
+            }
+            TransaktionsTyp::AnleiheVerkauf {
+                nominale,
+                kurs,
+                stückzinsen,
+                spesen,
+            } => {
+                writeln!(w, "{datum}: Verkauf {}", ReportNominale(*nominale, *kurs))?;
+                schreibe_fremdwährung(&mut w, "Kurs", fremdwährung, 4)?;
+                schreibe_stückzinsen(&mut w, *stückzinsen)?;
+                schreibe_spesen(&mut w, *spesen, transaktion.typ.geldfluss())?;
             }
-            TransaktionsTyp::Verkauf { stück, preis } => {
-                writeln!(w, "{datum}: Verkauf {}", ReportBestand(*stück, *preis))?;
+            TransaktionsTyp::Tilgung { nominale, kurs } => {
+                writeln!(w, "{datum}: Tilgung {}", ReportNominale(*nominale, *kurs))?;
+                schreibe_fremdwährung(&mut w, "Kurs", fremdwährung, 4)?;
             }
 
             TransaktionsTyp::Split { faktor } => {
                 writeln!(w, "{datum}: Aktiensplit mit Faktor {faktor}")?;
             }
-            TransaktionsTyp::Ausgliederung { faktor, isin } => {
-                writeln!(w, "{datum}: Ausgliederung von `{isin}` mit Faktor {faktor}")?;
+            TransaktionsTyp::Fondsverschmelzung { isin, faktor, .. } => {
+                writeln!(
+                    w,
+                    "{datum}: Verschmelzung in `{isin}` mit Umtauschverhältnis {faktor}"
+                )?;
+            }
+            TransaktionsTyp::Gratisaktien { stück } => {
+                writeln!(
+                    w,
+                    "{datum}: Kapitalerhöhung aus Gesellschaftsmitteln mit {stück} Gratisaktien"
+                )?;
             }
-            TransaktionsTyp::Einbuchung { stück, preis } => {
+            TransaktionsTyp::Aktiendividende {
+                stück,
+                preis,
+                quellensteuer,
+                quellensteuersatz,
+            } => {
                 writeln!(
                     w,
-                    "{datum}: Einbuchung nach Ausgliederung {}",
-                    ReportBestand(*stück, *preis)
+                    "{datum}: Dividende in Aktien {}",
+                    ReportPosten(typ, *stück, *preis)
+                )?;
+                schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
+                w.write_split("Brutto:", Eur(stück * preis, 2))?;
+                if !quellensteuer.is_zero() {
+                    w.write_split("Einbehaltene Quellensteuer:", Eur(*quellensteuer, 2))?;
+                }
+                schreibe_quellensteuersatz(&mut w, quellensteuersatz)?;
+            }
+            TransaktionsTyp::Ausgliederung {
+                faktor,
+                isin,
+                eigener_kurs,
+                anderer_kurs,
+            } => {
+                writeln!(w, "{datum}: Ausgliederung von `{isin}` mit Faktor {faktor}")?;
+                w.write_split_fmt(
+                    format_args!("Verkehrswert ({}):", eigener_kurs.quelle),
+                    Eur(eigener_kurs.wert, 4),
+                )?;
+                schreibe_fremdwährung(
+                    &mut w,
+                    "Verkehrswert",
+                    eigener_kurs.fremdwährung.as_ref(),
+                    4,
+                )?;
+                w.write_split_fmt(
+                    format_args!("Verkehrswert `{isin}` ({}):", anderer_kurs.quelle),
+                    Eur(anderer_kurs.wert, 4),
                 )?;
+                schreibe_fremdwährung(
+                    &mut w,
+                    &format!("Verkehrswert `{isin}`"),
+                    anderer_kurs.fremdwährung.as_ref(),
+                    4,
+                )?;
+            }
+            TransaktionsTyp::Einbuchung {
+                stück,
+                preis,
+                quelle,
+            } => {
+                if *quelle == Kursquelle::Ausgliederung {
+                    writeln!(
+                        w,
+                        "{datum}: Einbuchung nach Ausgliederung {}",
+                        ReportPosten(typ, *stück, *preis)
+                    )?;
+                } else {
+                    writeln!(
+                        w,
+                        "{datum}: Einbuchung {}",
+                        ReportPosten(typ, *stück, *preis)
+                    )?;
+                    schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
+                }
+                w.write_split("Preis laut:", quelle)?;
             }
             TransaktionsTyp::Spitzenverwertung { stück, preis } => {
                 writeln!(
                     w,
                     "{datum}: Spitzenverwertung {}",
-                    ReportBestand(*stück, *preis)
+                    ReportPosten(typ, *stück, *preis)
                 )?;
+                schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
             }
 
-            TransaktionsTyp::Dividende { auszahlung, .. } => {
+            TransaktionsTyp::Ausbuchung { stück, preis } => {
+                if preis.is_zero() {
+                    writeln!(
+                        w,
+                        "{datum}: Wertlose Ausbuchung {}",
+                        ReportPosten(typ, *stück, *preis)
+                    )?;
+                } else {
+                    writeln!(
+                        w,
+                        "{datum}: Ausbuchung gegen Abfindung {}",
+                        ReportPosten(typ, *stück, *preis)
+                    )?;
+                    schreibe_fremdwährung(&mut w, "Abfindung", fremdwährung, 4)?;
+                }
+            }
+
+            TransaktionsTyp::Dividende {
+                auszahlung,
+                quellensteuersatz,
+                ..
+            } => {
                 writeln!(w, "{datum}: Dividendenzahlung")?;
+                schreibe_fremdwährung(&mut w, "Brutto", fremdwährung, 2)?;
+                if let Some(
+                    f @ Fremdwährung {
+                        auszahlung: Some(ausgezahlt),
+                        ..
+                    },
+                ) = fremdwährung
+                {
+                    schreibe_in_währung(&mut w, "Auszahlung", *ausgezahlt, f, 2)?;
+                }
                 writeln!(w, "Auszahlung: {}", Eur(*auszahlung, 2))?;
+                schreibe_quellensteuersatz(&mut w, quellensteuersatz)?;
+            }
+            TransaktionsTyp::Staking { stück, preis } => {
+                writeln!(w, "{datum}: Staking {}", ReportPosten(typ, *stück, *preis))?;
+                schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
+            }
+            TransaktionsTyp::Stillhalterprämie { stück, preis } => {
+                writeln!(
+                    w,
+                    "{datum}: Stillhalterprämie für {} Stück zu {}",
+                    stück,
+                    Eur(*preis, 4)
+                )?;
+                schreibe_fremdwährung(&mut w, "Prämie", fremdwährung, 4)?;
+            }
+            TransaktionsTyp::Glattstellung { stück, preis } => {
+                writeln!(
+                    w,
+                    "{datum}: Glattstellung von {stück} Stück zu {}",
+                    Eur(*preis, 4)
+                )?;
+                schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
+            }
+            TransaktionsTyp::Verfall { stück } => {
+                writeln!(w, "{datum}: Wertloser Verfall von {stück} Stück")?;
+            }
+            TransaktionsTyp::Ausübung {
+                stück,
+                art,
+                basiswert,
+                basispreis,
+            } => {
+                writeln!(
+                    w,
+                    "{datum}: Ausübung von {stück} Stück ({art}) auf `{basiswert}` zu {}",
+                    Eur(*basispreis, 4)
+                )?;
+                schreibe_fremdwährung(&mut w, "Basispreis", fremdwährung, 4)?;
+            }
+            TransaktionsTyp::Rückerstattung { betrag, land, jahr } => {
+                writeln!(
+                    w,
+                    "{datum}: Rückerstattung von Quellensteuer ({land}, Dividenden {jahr})"
+                )?;
+                schreibe_fremdwährung(&mut w, "Auszahlung", fremdwährung, 2)?;
+                w.write_split("Auszahlung:", Eur(*betrag, 2))?;
+            }
+            TransaktionsTyp::Kupon { betrag } => {
+                writeln!(w, "{datum}: Zinszahlung")?;
+                schreibe_fremdwährung(&mut w, "Kupon", fremdwährung, 2)?;
+                w.write_split("Kupon:", Eur(*betrag, 2))?;
+            }
+            TransaktionsTyp::Einlagenrückzahlung { betrag, korrektur } => {
+                writeln!(w, "{datum}: Einlagenrückzahlung")?;
+                schreibe_fremdwährung(&mut w, "Auszahlung", fremdwährung, 2)?;
+                w.write_split("Auszahlung:", Eur(*betrag, 2))?;
+                w.write_split("Korrektur der Anschaffungskosten:", Eur(*korrektur, 4))?;
             }
             TransaktionsTyp::Ausschüttung { brutto, melde_id } => {
                 if let Some(melde_id) = melde_id {
@@ -120,24 +377,234 @@ pub fn schreibe_jahr<W: fmt::Write>(w: &mut W, jahr: &Jahr) -> fmt::Result {
             TransaktionsTyp::Jahresmeldung { melde_id } => {
                 writeln!(w, "{datum}: Jahresmeldung (Id: {melde_id})")?;
             }
+            TransaktionsTyp::Schätzung {
+                erster_kurs,
+                letzter_kurs,
+                ausgangswert,
+            } => {
+                writeln!(w, "{datum}: Keine Meldung – pauschal geschätzt")?;
+                if let Some(erster_kurs) = erster_kurs {
+                    w.write_split("Erster Rücknahmepreis:", Eur(*erster_kurs, 4))?;
+                }
+                w.write_split("Letzter Rücknahmepreis:", Eur(*letzter_kurs, 4))?;
+                w.write_split("Ausgangswert:", Eur(*ausgangswert, 2))?;
+            }
+            TransaktionsTyp::SchätzungVerkauf { stück, preis } => {
+                writeln!(
+                    w,
+                    "{datum}: Keine Meldung – pauschal geschätzt für den Verkauf {}",
+                    ReportPosten(typ, *stück, *preis)
+                )?;
+            }
+
+            TransaktionsTyp::Depotausgang { stück, depot } => {
+                writeln!(
+                    w,
+                    "{datum}: Depotübertrag {} nach `{depot}`",
+                    ReportPosten(typ, *stück, Zahl::zero())
+                )?;
+            }
+            TransaktionsTyp::Depoteingang {
+                stück,
+                preis,
+                depot,
+            } => {
+                writeln!(
+                    w,
+                    "{datum}: Depotübertrag {} von `{depot}`",
+                    ReportPosten(typ, *stück, *preis)
+                )?;
+            }
+
+            TransaktionsTyp::Umtausch {
+                stück,
+                isin,
+                neue_stück,
+                zuzahlung,
+            } => {
+                writeln!(
+                    w,
+                    "{datum}: Umtausch {} in {} `{isin}`",
+                    ReportPosten(typ, *stück, Zahl::zero()),
+                    ReportPosten(typ, *neue_stück, Zahl::zero())
+                )?;
+                if !zuzahlung.is_zero() {
+                    schreibe_fremdwährung(&mut w, "Zuzahlung", fremdwährung, 2)?;
+                    w.write_split("Zuzahlung:", Eur(*zuzahlung, 2))?;
+                }
+            }
+            TransaktionsTyp::Umtauscheingang {
+                stück,
+                preis,
+                isin: quelle,
+            } => {
+                writeln!(
+                    w,
+                    "{datum}: Umtausch {} aus `{quelle}`",
+                    ReportPosten(typ, *stück, *preis)
+                )?;
+            }
+
+            TransaktionsTyp::UnentgeltlicherErwerb {
+                stück,
+                preis,
+                anschaffung,
+            } => {
+                writeln!(
+                    w,
+                    "{datum}: Unentgeltlicher Erwerb {}",
+                    ReportPosten(typ, *stück, *preis)
+                )?;
+                schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
+                w.write_split("Anschaffung durch Rechtsvorgänger:", anschaffung)?;
+            }
+            TransaktionsTyp::Schenkung { stück } => {
+                writeln!(
+                    w,
+                    "{datum}: Unentgeltliche Übertragung {}",
+                    ReportPosten(typ, *stück, Zahl::zero())
+                )?;
+            }
+
+            TransaktionsTyp::Bezugsrecht { anzahl } => {
+                writeln!(w, "{datum}: Einbuchung von {anzahl} Bezugsrechten")?;
+                w.write_split("Anschaffungskosten:", Eur(Zahl::zero(), 2))?;
+            }
+            TransaktionsTyp::BezugsrechtVerkauf { anzahl, preis } => {
+                writeln!(
+                    w,
+                    "{datum}: Verkauf von Bezugsrechten {}",
+                    ReportPosten(typ, *anzahl, *preis)
+                )?;
+                schreibe_fremdwährung(&mut w, "Preis", fremdwährung, 4)?;
+            }
+            TransaktionsTyp::BezugsrechtAusübung {
+                anzahl,
+                stück,
+                preis,
+            } => {
+                writeln!(
+                    w,
+                    "{datum}: Bezug {} durch Ausübung von {anzahl} Bezugsrechten",
+                    ReportPosten(typ, *stück, *preis)
+                )?;
+                schreibe_fremdwährung(&mut w, "Bezugspreis", fremdwährung, 4)?;
+            }
         }
         print_steuern(&mut w, &transaktion.steuer)?;
 
         if !matches!(
             transaktion.typ,
             TransaktionsTyp::Dividende { .. }
+                | TransaktionsTyp::Kupon { .. }
+                | TransaktionsTyp::Rückerstattung { .. }
                 | TransaktionsTyp::Ausschüttung { melde_id: None, .. }
+                | TransaktionsTyp::SchätzungVerkauf { .. }
         ) {
-            w.write_split(
-                "Neuer Bestand:",
-                ReportBestand(bestand.stück, bestand.preis),
-            )?;
+            schreibe_bestand(&mut w, typ, format_args!("Neuer Bestand:"), bestand)?;
         }
     }
 
     w.divider('-')
 }
 
+impl fmt::Display for Kursquelle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kursquelle::Angegeben => "angegeben",
+            Kursquelle::Abgefragt => "Eröffnungskurs",
+            Kursquelle::Ausgliederung => "abgespaltene Anschaffungskosten",
+        })
+    }
+}
+
+fn schreibe_bestand<W: fmt::Write>(
+    w: &mut Writer<W>,
+    typ: WertpapierTyp,
+    links: fmt::Arguments,
+    bestand: Bestand,
+) -> fmt::Result {
+    if bestand.alt.stück.is_zero() {
+        w.write_split_fmt(links, ReportPosten(typ, bestand.stück, bestand.preis))?;
+    } else {
+        w.write_split_fmt(links, ReportPosten(typ, bestand.stück, Zahl::zero()))?;
+        w.write_split(
+            "  davon Neubestand:",
+            ReportPosten(typ, bestand.neu_stück(), bestand.preis),
+        )?;
+        w.write_split(
+            "  davon Altbestand:",
+            ReportPosten(typ, bestand.alt.stück, bestand.alt.preis),
+        )?;
+    }
+    if !bestand.bezugsrechte.is_zero() {
+        w.write_split("  Bezugsrechte:", bestand.bezugsrechte)?;
+    }
+    Ok(())
+}
+
+fn schreibe_spesen<W: fmt::Write>(w: &mut Writer<W>, spesen: Zahl, betrag: Zahl) -> fmt::Result {
+    if spesen.is_zero() {
+        return Ok(());
+    }
+    w.write_split("Spesen (steuerlich nicht abzugsfähig):", Eur(spesen, 2))?;
+    w.write_split("Zahlung inklusive Spesen:", Eur(betrag, 2))
+}
+
+fn schreibe_stückzinsen<W: fmt::Write>(w: &mut Writer<W>, stückzinsen: Zahl) -> fmt::Result {
+    if stückzinsen.is_zero() {
+        return Ok(());
+    }
+    w.write_split("Stückzinsen:", Eur(stückzinsen, 2))
+}
+
+fn schreibe_quellensteuersatz<W: fmt::Write>(
+    w: &mut Writer<W>,
+    quellensteuersatz: &Quellensteuersatz,
+) -> fmt::Result {
+    let Quellensteuersatz { land, satz, quelle } = quellensteuersatz;
+    let quelle = match quelle {
+        Satzquelle::Inland => "KeSt",
+        Satzquelle::Dba => "laut DBA",
+        Satzquelle::Angegeben => "angegeben",
+        Satzquelle::Pauschal => "pauschal, kein DBA bekannt",
+    };
+    w.write_split_fmt(
+        format_args!("Anrechenbarer Quellensteuersatz {land} ({quelle}):"),
+        format_args!("{} %", Dezimal(satz * 100, 1)),
+    )
+}
+
+fn schreibe_fremdwährung<W: fmt::Write>(
+    w: &mut Writer<W>,
+    was: &str,
+    fremdwährung: Option<&Fremdwährung>,
+    stellen: u32,
+) -> fmt::Result {
+    let Some(fremdwährung) = fremdwährung else {
+        return Ok(());
+    };
+    schreibe_in_währung(w, was, fremdwährung.betrag, fremdwährung, stellen)
+}
+
+/// Schreibt den ursprünglich angegebenen Betrag, statt ihn aus dem gerundeten EUR-Betrag
+/// zurückzurechnen.
+fn schreibe_in_währung<W: fmt::Write>(
+    w: &mut Writer<W>,
+    was: &str,
+    betrag: Zahl,
+    Fremdwährung { währung, kurs, .. }: &Fremdwährung,
+    stellen: u32,
+) -> fmt::Result {
+    w.write_split_fmt(
+        format_args!(
+            "{was} in {währung} (1 € = {} {währung}):",
+            Dezimal(*kurs, 4)
+        ),
+        Währung(betrag, währung, stellen),
+    )
+}
+
 pub struct Writer<W: fmt::Write> {
     inner: W,
     links: String,
@@ -199,6 +666,12 @@ pub fn print_steuern<W: Write>(w: &mut Writer<W>, steuer: &Steuer) -> fmt::Resul
                     Eur(verkauf.verluste_892, 2),
                 )?;
             }
+            if !verkauf.gewinn_altbestand.is_zero() {
+                w.write_split(
+                    "Nicht steuerpflichtiger Gewinn aus Altbestand:",
+                    Eur(verkauf.gewinn_altbestand, 2),
+                )?;
+            }
         }
         Steuer::Dividende(dividende) => {
             if !dividende.dividendenerträge_863.is_zero() {
@@ -219,6 +692,58 @@ pub fn print_steuern<W: Write>(w: &mut Writer<W>, steuer: &Steuer) -> fmt::Resul
                     Eur(dividende.anrechenbare_quellensteuer_998, 2),
                 )?;
             }
+            if !dividende.nicht_anrechenbare_quellensteuer.is_zero() {
+                w.write_split(
+                    "Im Quellenstaat rückforderbare Quellensteuer:",
+                    Eur(dividende.nicht_anrechenbare_quellensteuer, 2),
+                )?;
+            }
+        }
+        Steuer::Krypto(krypto) => {
+            if !krypto.laufende_einkünfte_171.is_zero() {
+                w.write_split(
+                    "Laufende Einkünfte aus Kryptowährungen (171):",
+                    Eur(krypto.laufende_einkünfte_171, 2),
+                )?;
+            }
+            if !krypto.überschüsse_172.is_zero() {
+                w.write_split(
+                    "Überschüsse aus Kryptowährungen (172):",
+                    Eur(krypto.überschüsse_172, 2),
+                )?;
+            }
+            if !krypto.verluste_173.is_zero() {
+                w.write_split(
+                    "Verluste aus Kryptowährungen (173):",
+                    Eur(krypto.verluste_173, 2),
+                )?;
+            }
+            if !krypto.gewinn_altbestand.is_zero() {
+                w.write_split(
+                    "Nicht steuerpflichtiger Gewinn aus Altvermögen:",
+                    Eur(krypto.gewinn_altbestand, 2),
+                )?;
+            }
+        }
+        Steuer::Derivat(derivat) => {
+            if !derivat.überschüsse_993.is_zero() {
+                w.write_split(
+                    "Überschüsse aus Derivaten (993):",
+                    Eur(derivat.überschüsse_993, 2),
+                )?;
+            }
+            if !derivat.verluste_896.is_zero() {
+                w.write_split(
+                    "Verluste aus Derivaten (896):",
+                    Eur(derivat.verluste_896, 2),
+                )?;
+            }
+            if !derivat.gewinn_altbestand.is_zero() {
+                w.write_split(
+                    "Nicht steuerpflichtiger Gewinn aus Altbestand:",
+                    Eur(derivat.gewinn_altbestand, 2),
+                )?;
+            }
         }
         Steuer::Ausschüttung(ausschüttung) => {
             if !ausschüttung.ausschüttungen_898.is_zero() {
@@ -244,53 +769,195 @@ pub fn print_steuern<W: Write>(w: &mut Writer<W>, steuer: &Steuer) -> fmt::Resul
     Ok(())
 }
 
-pub struct SteuerSumme {
+pub struct ReportRückforderungen<'a> {
+    pub rückforderungen: &'a Rückforderungen,
+    pub heute: Datum,
+}
+
+impl fmt::Display for ReportRückforderungen<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut w = Writer::new(f);
+
+        writeln!(w, "Rückforderbare Quellensteuer")?;
+        writeln!(w, "{:#<BREITE$}", "")?;
+
+        let mut summe = Zahl::zero();
+        for (&(land, jahr), rückforderung) in self.rückforderungen {
+            let frist = rückforderungsfrist(land, jahr);
+            let offen = rückforderung.offen();
+
+            writeln!(w)?;
+            writeln!(w, "{land}, Dividenden {jahr}, Frist bis {frist}")?;
+            w.write_split("Rückforderbar:", Eur(rückforderung.rückforderbar, 2))?;
+            w.write_split("Rückerstattet:", Eur(rückforderung.rückerstattet, 2))?;
+            if offen.is_zero() {
+                w.write_split("Offen:", Eur(offen, 2))?;
+            } else if frist < self.heute {
+                w.write_split("Offen (Frist abgelaufen):", Eur(offen, 2))?;
+            } else {
+                w.write_split("Offen:", Eur(offen, 2))?;
+                summe += offen;
+            }
+        }
+
+        writeln!(w)?;
+        w.write_split("Summe noch rückforderbar:", Eur(summe, 2))
+    }
+}
+
+fn schreibe_kennzahlen<W: fmt::Write>(
+    w: &mut Writer<W>,
+    titel: &str,
+    kennzahlen: &[(&str, Zahl)],
+) -> fmt::Result {
+    writeln!(w)?;
+    writeln!(w, "{titel}:")?;
+    for (kennzahl, betrag) in kennzahlen {
+        w.write_split_fmt(format_args!("  {kennzahl}:"), Eur(*betrag, 2))?;
+    }
+    Ok(())
+}
+
+pub struct SteuerSumme<'a> {
     pub summe: SteuerJahr,
+    /// Die Zusammenfassung für ein einzelnes Depot, oder über alle Depots bei `None`.
+    pub depot: Option<Option<&'a str>>,
 }
 
-impl fmt::Display for SteuerSumme {
+impl fmt::Display for SteuerSumme<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let steuer = self.summe;
         let mut w = Writer::new(f);
 
-        writeln!(w, "Zusammenfassung für Jahr {}", steuer.jahr)?;
+        write!(w, "Zusammenfassung für Jahr {}", steuer.jahr)?;
+        if let Some(depot) = self.depot {
+            writeln!(w, ", Depot: {}", depot.unwrap_or(OHNE_DEPOT))?;
+        } else {
+            writeln!(w)?;
+        }
         writeln!(w)?;
 
-        w.write_split(
-            "Überschüsse aus realisierten Wertsteigerungen (994):",
-            Eur(steuer.überschüsse_994, 2),
-        )?;
-        w.write_split(
-            "Verluste aus realisierten Wertverlusten (892):",
-            Eur(steuer.verluste_892, 2),
+        let prozent = |satz: Zahl| Dezimal(satz * 100, 1);
+        writeln!(
+            w,
+            "Einkünfte mit besonderem Steuersatz von {} % (E1kv):",
+            prozent(steuer.steuersatz())
         )?;
 
-        writeln!(w)?;
-        w.write_split(
-            "Einkünfte aus Dividenden (863):",
-            Eur(steuer.dividendenerträge_863, 2),
+        schreibe_kennzahlen(
+            &mut w,
+            "Überlassung von Kapital (Dividenden, Zinsen)",
+            &[
+                ("Inland (862)", steuer.dividendenerträge_862),
+                ("Ausland (863)", steuer.dividendenerträge_863),
+            ],
         )?;
-
-        writeln!(w)?;
-        w.write_split("Ausschüttungen (898):", Eur(steuer.ausschüttungen_898, 2))?;
-        w.write_split(
-            "Ausschüttungsgleiche Erträge (937):",
-            Eur(steuer.ausschüttungsgleiche_erträge_937, 2),
+        schreibe_kennzahlen(
+            &mut w,
+            "Investmentfonds",
+            &[
+                ("Ausschüttungen Inland (897)", steuer.ausschüttungen_897),
+                ("Ausschüttungen Ausland (898)", steuer.ausschüttungen_898),
+                (
+                    "Ausschüttungsgleiche Erträge Inland (936)",
+                    steuer.ausschüttungsgleiche_erträge_936,
+                ),
+                (
+                    "Ausschüttungsgleiche Erträge Ausland (937)",
+                    steuer.ausschüttungsgleiche_erträge_937,
+                ),
+            ],
+        )?;
+        schreibe_kennzahlen(
+            &mut w,
+            "Realisierte Wertsteigerungen",
+            &[
+                ("Überschüsse Inland (981)", steuer.überschüsse_981),
+                ("Überschüsse Ausland (994)", steuer.überschüsse_994),
+                ("Verluste Inland (891)", steuer.verluste_891),
+                ("Verluste Ausland (892)", steuer.verluste_892),
+            ],
+        )?;
+        schreibe_kennzahlen(
+            &mut w,
+            "Derivate",
+            &[
+                ("Überschüsse Inland (982)", steuer.derivate_überschüsse_982),
+                ("Überschüsse Ausland (993)", steuer.derivate_überschüsse_993),
+                ("Verluste Inland (893)", steuer.derivate_verluste_893),
+                ("Verluste Ausland (896)", steuer.derivate_verluste_896),
+            ],
+        )?;
+        schreibe_kennzahlen(
+            &mut w,
+            "Kryptowährungen",
+            &[
+                (
+                    "Laufende Einkünfte Inland (174)",
+                    steuer.krypto_laufende_einkünfte_174,
+                ),
+                (
+                    "Laufende Einkünfte Ausland (171)",
+                    steuer.krypto_laufende_einkünfte_171,
+                ),
+                ("Überschüsse Inland (175)", steuer.krypto_überschüsse_175),
+                ("Überschüsse Ausland (172)", steuer.krypto_überschüsse_172),
+                ("Verluste Inland (176)", steuer.krypto_verluste_176),
+                ("Verluste Ausland (173)", steuer.krypto_verluste_173),
+            ],
+        )?;
+        schreibe_kennzahlen(
+            &mut w,
+            "Anrechnung",
+            &[
+                (
+                    "Gezahlte inländische KeSt (899)",
+                    steuer.gezahlte_inländische_kest_899,
+                ),
+                (
+                    "Anrechenbare ausländische Quellensteuer (998)",
+                    steuer.anrechenbare_quellensteuer_998,
+                ),
+            ],
         )?;
 
+        let ausgleich = steuer.verlustausgleich();
         writeln!(w)?;
+        writeln!(w, "Verlustausgleich laut § 27 (8):")?;
+        w.write_split("Verluste:", Eur(ausgleich.verluste, 2))?;
+        for schritt in &ausgleich.schritte {
+            w.write_split_fmt(
+                format_args!("- {}:", schritt.kennzahl),
+                Eur(-schritt.ausgeglichen, 2),
+            )?;
+        }
+        if !ausgleich.verfallen.is_zero() {
+            w.write_split(
+                "Nicht ausgleichbare Verluste (verfallen):",
+                Eur(ausgleich.verfallen, 2),
+            )?;
+        }
         w.write_split(
-            "Gezahlte inländische KeSt (899):",
-            Eur(steuer.gezahlte_inländische_kest_899, 2),
+            "Einkünfte nach Verlustausgleich:",
+            Eur(ausgleich.bemessungsgrundlage, 2),
+        )?;
+        w.write_split_fmt(
+            format_args!("Steuer ({} %):", prozent(steuer.steuersatz())),
+            Eur(ausgleich.steuer, 2),
         )?;
+        if ausgleich.angerechnete_quellensteuer < steuer.anrechenbare_quellensteuer_998 {
+            w.write_split(
+                "Höchstbetrag der anrechenbaren Quellensteuer:",
+                Eur(ausgleich.höchstbetrag_998, 2),
+            )?;
+        }
         w.write_split(
-            "Anrechenbare ausländische Quellensteuer (998):",
-            Eur(steuer.anrechenbare_quellensteuer_998, 2),
+            "Angerechnete Quellensteuer:",
+            Eur(ausgleich.angerechnete_quellensteuer, 2),
         )?;
 
-        let nachzahlung = steuer.berechne_nachzahlung();
         writeln!(w)?;
-        w.write_split("Steuernachzahlung:", Eur(nachzahlung, 2))?;
+        w.write_split("Steuernachzahlung:", Eur(ausgleich.nachzahlung, 2))?;
 
         Ok(())
     }
@@ -1,36 +1,84 @@
-use std::ops::AddAssign;
-
 use num_traits::identities::Zero;
 
+use crate::dba::{Land, Satzquelle};
 use crate::meldungen::FondMeldung;
-use crate::{Bestand, SteuerJahr, String, TransaktionsTyp, Zahl};
-use crate::{Steuer, SteuerAusschüttung, SteuerDividende, SteuerVerkauf};
+use crate::{Altbestand, Bestand, Datum, Kursquelle, Optionsart, Quellensteuersatz};
+use crate::{Steuer, SteuerAusschüttung, SteuerDerivat, SteuerDividende, SteuerKrypto};
+use crate::{SteuerJahr, String};
+use crate::{SteuerVerkauf, TransaktionsTyp};
+use crate::{Verkehrswert, Zahl};
 
-type Ergebnis = (Bestand, TransaktionsTyp, Steuer);
+pub type Ergebnis = (Bestand, TransaktionsTyp, Steuer);
 
 // Laut § 27a (4) 3. gilt:
 // [B]ei Erwerb in zeitlicher Aufeinanderfolge [ist] der gleitende
 // Durchschnittspreis […] anzusetzen.
-pub fn kauf_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl) -> Ergebnis {
-    let stück_neu = bestand.stück + stück;
-    let preis_neu = runde((bestand.summe() + (stück * preis)) / stück_neu, 4);
+//
+// Laut § 27a (4) 2. gilt:
+// Bei Wirtschaftsgütern und Derivaten […] sind die Anschaffungskosten ohne
+// Anschaffungsnebenkosten anzusetzen.
+// Die Spesen werden daher nur für die Ausgabe mitgeführt.
+pub fn kauf_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl, spesen: Zahl) -> Ergebnis {
+    let zugang = Bestand {
+        stück,
+        preis,
+        ..Default::default()
+    };
 
     (
-        Bestand {
-            stück: stück_neu,
-            preis: preis_neu,
+        zugang_berechnen(bestand, zugang),
+        TransaktionsTyp::Kauf {
+            stück,
+            preis,
+            spesen,
+        },
+        Steuer::Keine,
+    )
+}
+
+// Laut § 124b Z 185 lit. a fallen vor dem 1. Jänner 2011 angeschaffte Aktien und
+// Anteile an Investmentfonds nicht unter die neue Besteuerung von realisierten
+// Wertsteigerungen. Diese werden als eigener Altbestand mit eigenem
+// Durchschnittspreis geführt.
+pub fn kauf_altbestand_berechnen(
+    bestand: Bestand,
+    stück: Zahl,
+    preis: Zahl,
+    spesen: Zahl,
+) -> Ergebnis {
+    let zugang = Bestand {
+        stück,
+        preis: Zahl::zero(),
+        alt: Altbestand { stück, preis },
+        ..Default::default()
+    };
+
+    (
+        zugang_berechnen(bestand, zugang),
+        TransaktionsTyp::Kauf {
+            stück,
+            preis,
+            spesen,
         },
-        TransaktionsTyp::Kauf { stück, preis },
         Steuer::Keine,
     )
 }
 
 // Das gleiche wie ein Kauf, nur wollen wir einen anderen Typ für die Ausgabe
-pub fn einbuchung_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl) -> Ergebnis {
-    let (bestand, _transaktion, steuer) = kauf_berechnen(bestand, stück, preis);
+pub fn einbuchung_berechnen(
+    bestand: Bestand,
+    stück: Zahl,
+    preis: Zahl,
+    quelle: Kursquelle,
+) -> Ergebnis {
+    let (bestand, _transaktion, steuer) = kauf_berechnen(bestand, stück, preis, Zahl::zero());
     (
         bestand,
-        TransaktionsTyp::Einbuchung { stück, preis },
+        TransaktionsTyp::Einbuchung {
+            stück,
+            preis,
+            quelle,
+        },
         steuer,
     )
 }
@@ -39,14 +87,21 @@ pub fn einbuchung_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl) -> Erge
 // Als Einkünfte anzusetzen sind
 // [b]ei realisierten Wertsteigerungen […] der Unterschiedsbetrag
 // zwischen dem Veräußerungserlös […] und den Anschaffungskosten.
-pub fn verkauf_berechnen(mut bestand: Bestand, stück: Zahl, preis: Zahl) -> Ergebnis {
-    let einstand = stück * bestand.preis;
-    let erlös = stück * preis;
+//
+// Laut § 20 (2) dürfen Aufwendungen und Ausgaben, die mit Einkünften, auf die der
+// besondere Steuersatz […] anwendbar ist, in unmittelbarem wirtschaftlichem
+// Zusammenhang stehen, nicht abgezogen werden.
+// Die Spesen mindern daher den Veräußerungserlös nicht.
+//
+// Der Gewinn aus dem Altbestand ist nicht steuerpflichtig, und wird nur für die
+// Ausgabe mitgeführt.
+pub fn verkauf_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl, spesen: Zahl) -> Ergebnis {
+    let mut bestand = bestand;
+    let abgang = abgang_berechnen(&mut bestand, stück);
 
-    bestand.stück -= stück;
-    if bestand.stück.is_zero() {
-        bestand.preis = 0.into();
-    }
+    let neu_stück = abgang.neu_stück();
+    let einstand = neu_stück * abgang.preis;
+    let erlös = neu_stück * preis;
 
     let mut steuer = SteuerVerkauf::default();
     if erlös > einstand {
@@ -54,17 +109,22 @@ pub fn verkauf_berechnen(mut bestand: Bestand, stück: Zahl, preis: Zahl) -> Erg
     } else {
         steuer.verluste_892 = einstand - erlös;
     }
+    steuer.gewinn_altbestand = abgang.alt.stück * (preis - abgang.alt.preis);
 
     (
         bestand,
-        TransaktionsTyp::Verkauf { stück, preis },
+        TransaktionsTyp::Verkauf {
+            stück,
+            preis,
+            spesen,
+        },
         Steuer::Verkauf(steuer),
     )
 }
 
 // Das gleiche wie ein Verkauf, nur wollen wir einen anderen Typ für die Ausgabe
 pub fn spitzenverwertung_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl) -> Ergebnis {
-    let (bestand, _transaktion, steuer) = verkauf_berechnen(bestand, stück, preis);
+    let (bestand, _transaktion, steuer) = verkauf_berechnen(bestand, stück, preis, Zahl::zero());
     (
         bestand,
         TransaktionsTyp::Spitzenverwertung { stück, preis },
@@ -72,6 +132,268 @@ pub fn spitzenverwertung_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl)
     )
 }
 
+// Die Ausbuchung wertlos gewordener Wertpapiere ist wie eine Veräußerung zum Preis von Null,
+// bzw. zur erhaltenen Abfindung, zu behandeln. Der Verlust ist daher ausgleichsfähig.
+pub fn ausbuchung_berechnen(bestand: Bestand, preis: Zahl) -> Ergebnis {
+    let stück = bestand.stück;
+    let (mut bestand, _transaktion, steuer) =
+        verkauf_berechnen(bestand, stück, preis, Zahl::zero());
+    bestand.bezugsrechte = Zahl::zero();
+    (
+        bestand,
+        TransaktionsTyp::Ausbuchung { stück, preis },
+        steuer,
+    )
+}
+
+// Laut § 27 (6) 2. gilt zwar das Ausscheiden aus dem Depot als Veräußerung, nicht aber
+// die Übertragung auf ein anderes Depot desselben Steuerpflichtigen.
+// Die Stück werden mitsamt ihren Anschaffungskosten übertragen.
+pub fn depotausgang_berechnen(
+    mut bestand: Bestand,
+    stück: Zahl,
+    depot: String,
+) -> (Ergebnis, Bestand) {
+    let übertrag = abgang_berechnen(&mut bestand, stück);
+
+    (
+        (
+            bestand,
+            TransaktionsTyp::Depotausgang { stück, depot },
+            Steuer::Keine,
+        ),
+        übertrag,
+    )
+}
+
+// Die übertragenen Stück gehen laut § 27a (4) 3. in den gleitenden Durchschnittspreis
+// des Zieldepots ein.
+pub fn depoteingang_berechnen(bestand: Bestand, übertrag: Bestand, depot: String) -> Ergebnis {
+    (
+        zugang_berechnen(bestand, übertrag),
+        TransaktionsTyp::Depoteingang {
+            stück: übertrag.stück,
+            preis: runde(übertrag.summe() / übertrag.stück, 4),
+            depot,
+        },
+        Steuer::Keine,
+    )
+}
+
+// Laut § 5 (1) UmgrStG gilt der Austausch von Anteilen im Zuge einer Verschmelzung nicht als
+// Tausch. Die Anschaffungskosten der hingegebenen Anteile gehen auf die neuen Anteile über,
+// und auch die Zugehörigkeit zum Altbestand bleibt erhalten.
+// Bare Zuzahlungen werden zur Gänze als Veräußerungserlös angesetzt, soweit sie nicht auf den
+// Altbestand entfallen.
+pub fn umtausch_berechnen(
+    mut bestand: Bestand,
+    stück: Zahl,
+    isin: String,
+    neue_stück: Zahl,
+    zuzahlung: Zahl,
+) -> (Ergebnis, Bestand) {
+    let abgang = abgang_berechnen(&mut bestand, stück);
+    let faktor = neue_stück / stück;
+    let übertrag = Bestand {
+        stück: neue_stück,
+        preis: runde(abgang.preis / faktor, 4),
+        alt: Altbestand {
+            stück: abgang.alt.stück * faktor,
+            preis: runde(abgang.alt.preis / faktor, 4),
+        },
+        ..Default::default()
+    };
+
+    let gewinn_altbestand = runde(zuzahlung * abgang.alt.stück / stück, 2);
+    let steuer = if zuzahlung.is_zero() {
+        Steuer::Keine
+    } else {
+        Steuer::Verkauf(SteuerVerkauf {
+            überschüsse_994: zuzahlung - gewinn_altbestand,
+            gewinn_altbestand,
+            ..Default::default()
+        })
+    };
+
+    (
+        (
+            bestand,
+            TransaktionsTyp::Umtausch {
+                stück,
+                isin,
+                neue_stück,
+                zuzahlung,
+            },
+            steuer,
+        ),
+        übertrag,
+    )
+}
+
+pub fn umtauscheingang_berechnen(bestand: Bestand, übertrag: Bestand, isin: String) -> Ergebnis {
+    (
+        zugang_berechnen(bestand, übertrag),
+        TransaktionsTyp::Umtauscheingang {
+            stück: übertrag.stück,
+            preis: runde(übertrag.summe() / übertrag.stück, 4),
+            isin,
+        },
+        Steuer::Keine,
+    )
+}
+
+// Laut § 27a (4) 1. sind bei unentgeltlich erworbenen Wirtschaftsgütern die
+// Anschaffungskosten des Rechtsvorgängers maßgeblich.
+// Auch die Zugehörigkeit zum Altbestand richtet sich nach dessen Anschaffung.
+pub fn unentgeltlicher_erwerb_berechnen(
+    bestand: Bestand,
+    stück: Zahl,
+    preis: Zahl,
+    anschaffung: Datum,
+    altbestand: bool,
+) -> Ergebnis {
+    let zugang = if altbestand {
+        Bestand {
+            stück,
+            preis: Zahl::zero(),
+            alt: Altbestand { stück, preis },
+            ..Default::default()
+        }
+    } else {
+        Bestand {
+            stück,
+            preis,
+            ..Default::default()
+        }
+    };
+
+    (
+        zugang_berechnen(bestand, zugang),
+        TransaktionsTyp::UnentgeltlicherErwerb {
+            stück,
+            preis,
+            anschaffung,
+        },
+        Steuer::Keine,
+    )
+}
+
+// Laut § 27 (6) 2. gilt die unentgeltliche Übertragung nicht als Veräußerung,
+// die Stück scheiden ohne Besteuerung aus dem Bestand aus.
+pub fn schenkung_berechnen(mut bestand: Bestand, stück: Zahl) -> Ergebnis {
+    abgang_berechnen(&mut bestand, stück);
+
+    (bestand, TransaktionsTyp::Schenkung { stück }, Steuer::Keine)
+}
+
+// Laut § 5 KapMaßnVO gilt:
+// Auf einem Wertpapierdepot eingebuchte Bezugsrechte sind für Zwecke des
+// Kapitalertragsteuerabzuges mit einem Wert von Null anzusetzen. Die Anschaffungskosten
+// der Aktien, die die Bezugsrechte vermitteln, bleiben unberührt.
+pub fn bezugsrecht_berechnen(mut bestand: Bestand, anzahl: Zahl) -> Ergebnis {
+    bestand.bezugsrechte += anzahl;
+
+    (
+        bestand,
+        TransaktionsTyp::Bezugsrecht { anzahl },
+        Steuer::Keine,
+    )
+}
+
+// Da die Bezugsrechte mit Null anzusetzen sind, ist der gesamte Erlös ein Überschuss.
+pub fn bezugsrecht_verkauf_berechnen(mut bestand: Bestand, anzahl: Zahl, preis: Zahl) -> Ergebnis {
+    bestand.bezugsrechte -= anzahl;
+
+    let steuer = SteuerVerkauf {
+        überschüsse_994: anzahl * preis,
+        ..Default::default()
+    };
+
+    (
+        bestand,
+        TransaktionsTyp::BezugsrechtVerkauf { anzahl, preis },
+        Steuer::Verkauf(steuer),
+    )
+}
+
+// Die bezogenen Aktien werden mit dem Bezugspreis angeschafft, da die ausgeübten
+// Bezugsrechte mit Null anzusetzen sind.
+pub fn bezugsrecht_ausübung_berechnen(
+    mut bestand: Bestand,
+    anzahl: Zahl,
+    stück: Zahl,
+    preis: Zahl,
+) -> Ergebnis {
+    bestand.bezugsrechte -= anzahl;
+    let zugang = Bestand {
+        stück,
+        preis,
+        ..Default::default()
+    };
+
+    (
+        zugang_berechnen(bestand, zugang),
+        TransaktionsTyp::BezugsrechtAusübung {
+            anzahl,
+            stück,
+            preis,
+        },
+        Steuer::Keine,
+    )
+}
+
+/// Fügt einen Zugang getrennt nach Neu- und Altbestand mit gleitendem Durchschnittspreis hinzu.
+pub(crate) fn zugang_berechnen(bestand: Bestand, zugang: Bestand) -> Bestand {
+    let neu_stück = bestand.neu_stück() + zugang.neu_stück();
+    let neu_summe = bestand.neu_stück() * bestand.preis + zugang.neu_stück() * zugang.preis;
+    let alt_stück = bestand.alt.stück + zugang.alt.stück;
+    let alt_summe = bestand.alt.summe() + zugang.alt.summe();
+
+    Bestand {
+        stück: bestand.stück + zugang.stück,
+        preis: durchschnitt(neu_stück, neu_summe),
+        alt: Altbestand {
+            stück: alt_stück,
+            preis: durchschnitt(alt_stück, alt_summe),
+        },
+        bezugsrechte: bestand.bezugsrechte + zugang.bezugsrechte,
+    }
+}
+
+fn durchschnitt(stück: Zahl, summe: Zahl) -> Zahl {
+    if stück.is_zero() {
+        Zahl::zero()
+    } else {
+        runde(summe / stück, 4)
+    }
+}
+
+/// Entfernt Stück aus dem Bestand, wobei zuerst der Altbestand als veräußert gilt.
+/// Gibt die entfernten Stück mit ihren jeweiligen Anschaffungskosten zurück.
+fn abgang_berechnen(bestand: &mut Bestand, stück: Zahl) -> Bestand {
+    let alt_stück = stück.min(bestand.alt.stück);
+    let abgang = Bestand {
+        stück,
+        preis: bestand.preis,
+        alt: Altbestand {
+            stück: alt_stück,
+            preis: bestand.alt.preis,
+        },
+        ..Default::default()
+    };
+
+    bestand.stück -= stück;
+    bestand.alt.stück -= alt_stück;
+    if bestand.neu_stück().is_zero() {
+        bestand.preis = Zahl::zero();
+    }
+    if bestand.alt.stück.is_zero() {
+        bestand.alt.preis = Zahl::zero();
+    }
+
+    abgang
+}
+
 // Laut § 6 (1) gilt:
 // Wird im Zuge eines Aktiensplits der Nennwert einer Aktie heruntergesetzt und daher
 // die Anzahl der ausgegebenen Aktien erhöht, sind die bisherigen Anschaffungskosten auf die
@@ -79,47 +401,154 @@ pub fn spitzenverwertung_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl)
 pub fn split_berechnen(mut bestand: Bestand, faktor: Zahl) -> Ergebnis {
     bestand.stück *= faktor;
     bestand.preis = runde(bestand.preis / faktor, 4);
+    bestand.alt.stück *= faktor;
+    bestand.alt.preis = runde(bestand.alt.preis / faktor, 4);
 
     (bestand, TransaktionsTyp::Split { faktor }, Steuer::Keine)
 }
 
+// Bei der Verschmelzung von Investmentfonds gilt der Umtausch der Anteile nicht als
+// Realisierung. Die Anschaffungskosten gehen im Umtauschverhältnis auf die Anteile am
+// übernehmenden Fonds über.
+pub fn fondsverschmelzung_berechnen(
+    bestand: Bestand,
+    isin: String,
+    symbol: Option<String>,
+    faktor: Zahl,
+) -> Ergebnis {
+    let (bestand, _transaktion, steuer) = split_berechnen(bestand, faktor);
+
+    (
+        bestand,
+        TransaktionsTyp::Fondsverschmelzung {
+            isin,
+            symbol,
+            faktor,
+        },
+        steuer,
+    )
+}
+
+// Laut § 3 KapBG sind bei einer Kapitalerhöhung aus Gesellschaftsmitteln die Anschaffungskosten
+// der bisherigen Anteile auf die bisherigen und die neuen Anteile zu verteilen.
+// Die Gratisaktien werden daher wie bei einem Aktiensplit behandelt.
+pub fn gratisaktien_berechnen(bestand: Bestand, stück: Zahl) -> Ergebnis {
+    let faktor = (bestand.stück + stück) / bestand.stück;
+    let (bestand, _transaktion, steuer) = split_berechnen(bestand, faktor);
+
+    (bestand, TransaktionsTyp::Gratisaktien { stück }, steuer)
+}
+
+// Eine Dividende in Form von Aktien ist mit dem Wert der erhaltenen Aktien als Dividende
+// zu versteuern, welcher damit auch als deren Anschaffungskosten gilt.
+pub fn aktiendividende_berechnen(
+    bestand: Bestand,
+    quellensteuersatz: Quellensteuersatz,
+    stück: Zahl,
+    preis: Zahl,
+    quellensteuer: Zahl,
+) -> Ergebnis {
+    let brutto = stück * preis;
+    let (bestand, _transaktion, steuer) =
+        dividende_berechnen(bestand, quellensteuersatz, brutto, brutto - quellensteuer);
+    let (bestand, _transaktion, _) = kauf_berechnen(bestand, stück, preis, Zahl::zero());
+
+    (
+        bestand,
+        TransaktionsTyp::Aktiendividende {
+            stück,
+            preis,
+            quellensteuer,
+            quellensteuersatz,
+        },
+        steuer,
+    )
+}
+
 // Laut § 4 (2) gilt:
 // Werden Aktien im Zuge einer Abspaltung auf ein Wertpapierdepot eingebucht,
 // sind für Zwecke des Kapitalertragsteuerabzuges die Anschaffungskosten der vor
 // der Abspaltung bestehenden Aktien auf die bestehenden und die neu eingebuchten
 // Aktien aufzuteilen. Diese Aufteilung hat im Verhältnis der Verkehrswerte der
 // bestehenden zu den eingebuchten Aktien zu erfolgen.
+//
+// Die abgespaltenen Anschaffungskosten gehen zur Gänze auf die eingebuchten Aktien über,
+// welche auch die Zugehörigkeit zum Altbestand beibehalten.
 pub fn ausgliederung_berechnen(
     mut bestand: Bestand,
     faktor: Zahl,
     isin: String,
-    eigener_kurs: Zahl,
-    anderer_kurs: Zahl,
-) -> Ergebnis {
+    eigener_kurs: Verkehrswert,
+    anderer_kurs: Verkehrswert,
+) -> (Ergebnis, Bestand) {
+    let vorher = bestand;
     let eigener_bestand = Bestand {
         stück: bestand.stück,
-        preis: eigener_kurs,
+        preis: eigener_kurs.wert,
+        ..Default::default()
     };
     let anderer_bestand = Bestand {
         stück: bestand.stück * faktor,
-        preis: anderer_kurs,
+        preis: anderer_kurs.wert,
+        ..Default::default()
     };
 
     let gesamtwert = eigener_bestand.summe() + anderer_bestand.summe();
     let prozentual = eigener_bestand.summe() / gesamtwert;
 
     bestand.preis = runde(bestand.preis * prozentual, 4);
+    bestand.alt.preis = runde(bestand.alt.preis * prozentual, 4);
+
+    let neu_stück = vorher.neu_stück() * faktor;
+    let alt_stück = vorher.alt.stück * faktor;
+    let abgespalten = Bestand {
+        stück: neu_stück + alt_stück,
+        preis: durchschnitt(
+            neu_stück,
+            vorher.neu_stück() * (vorher.preis - bestand.preis),
+        ),
+        alt: Altbestand {
+            stück: alt_stück,
+            preis: durchschnitt(alt_stück, vorher.alt.summe() - bestand.alt.summe()),
+        },
+        ..Default::default()
+    };
 
     (
-        bestand,
-        TransaktionsTyp::Ausgliederung { faktor, isin },
+        (
+            bestand,
+            TransaktionsTyp::Ausgliederung {
+                faktor,
+                isin,
+                eigener_kurs,
+                anderer_kurs,
+            },
+            Steuer::Keine,
+        ),
+        abgespalten,
+    )
+}
+
+// Die im Zuge einer Ausgliederung eingebuchten Aktien übernehmen die abgespaltenen
+// Anschaffungskosten.
+pub fn einbuchung_ausgliederung_berechnen(bestand: Bestand, abgespalten: Bestand) -> Ergebnis {
+    (
+        zugang_berechnen(bestand, abgespalten),
+        TransaktionsTyp::Einbuchung {
+            stück: abgespalten.stück,
+            preis: runde(abgespalten.summe() / abgespalten.stück, 4),
+            quelle: Kursquelle::Ausgliederung,
+        },
         Steuer::Keine,
     )
 }
 
+// Die ausländische Quellensteuer ist laut dem DBA mit dem Land des Emittenten nur bis zu
+// dessen Satz anrechenbar, eine darüber hinaus einbehaltene Quellensteuer kann nur im
+// Quellenstaat zurückgefordert werden.
 pub fn dividende_berechnen(
     bestand: Bestand,
-    isin: &str,
+    quellensteuersatz: Quellensteuersatz,
     brutto: Zahl,
     auszahlung: Zahl,
 ) -> Ergebnis {
@@ -129,25 +558,293 @@ pub fn dividende_berechnen(
     };
 
     let gezahlte_quellensteuer = brutto - auszahlung;
-    if isin.starts_with("AT") {
+    if quellensteuersatz.quelle == Satzquelle::Inland {
         // sind die quellensteuern für AT aktien im ausland jetzt 899 oder 998?
         steuer.gezahlte_inländische_kest_899 = gezahlte_quellensteuer;
     } else {
-        // TODO: hängt dies vom Land ab?
-        let anrechenbarer_quellensteuersatz = Zahl::new(15, 100);
         steuer.anrechenbare_quellensteuer_998 = runde(
-            (brutto * anrechenbarer_quellensteuersatz).min(gezahlte_quellensteuer),
+            (brutto * quellensteuersatz.satz).min(gezahlte_quellensteuer),
             2,
         );
+        steuer.nicht_anrechenbare_quellensteuer =
+            gezahlte_quellensteuer - steuer.anrechenbare_quellensteuer_998;
     }
 
     (
         bestand,
-        TransaktionsTyp::Dividende { brutto, auszahlung },
+        TransaktionsTyp::Dividende {
+            brutto,
+            auszahlung,
+            quellensteuersatz,
+        },
         Steuer::Dividende(steuer),
     )
 }
 
+// Da nur die laut DBA anrechenbare Quellensteuer angerechnet wurde, ist die Rückerstattung der
+// darüber hinaus einbehaltenen Quellensteuer nicht steuerpflichtig.
+pub fn rückerstattung_berechnen(
+    bestand: Bestand,
+    betrag: Zahl,
+    land: Land,
+    jahr: i32,
+) -> Ergebnis {
+    (
+        bestand,
+        TransaktionsTyp::Rückerstattung { betrag, land, jahr },
+        Steuer::Keine,
+    )
+}
+
+// Anleihen werden in Einheiten zu 100 Nominale geführt, womit der Durchschnittspreis dem
+// Kurs in Prozent entspricht.
+//
+// Laut § 27 (6) 5. sind Stückzinsen Teil des Veräußerungserlöses. Die beim Kauf gezahlten
+// Stückzinsen erhöhen daher die Anschaffungskosten, und die beim Verkauf erhaltenen
+// Stückzinsen den Veräußerungserlös.
+pub fn anleihe_kauf_berechnen(
+    bestand: Bestand,
+    nominale: Zahl,
+    kurs: Zahl,
+    stückzinsen: Zahl,
+    spesen: Zahl,
+    altbestand: bool,
+) -> Ergebnis {
+    let stück = nominale / 100;
+    let preis = kurs + stückzinsen / stück;
+    let (bestand, _transaktion, steuer) = if altbestand {
+        kauf_altbestand_berechnen(bestand, stück, preis, spesen)
+    } else {
+        kauf_berechnen(bestand, stück, preis, spesen)
+    };
+    (
+        bestand,
+        TransaktionsTyp::AnleiheKauf {
+            nominale,
+            kurs,
+            stückzinsen,
+            spesen,
+        },
+        steuer,
+    )
+}
+
+pub fn anleihe_verkauf_berechnen(
+    bestand: Bestand,
+    nominale: Zahl,
+    kurs: Zahl,
+    stückzinsen: Zahl,
+    spesen: Zahl,
+) -> Ergebnis {
+    let stück = nominale / 100;
+    let preis = kurs + stückzinsen / stück;
+    let (bestand, _transaktion, steuer) = verkauf_berechnen(bestand, stück, preis, spesen);
+    (
+        bestand,
+        TransaktionsTyp::AnleiheVerkauf {
+            nominale,
+            kurs,
+            stückzinsen,
+            spesen,
+        },
+        steuer,
+    )
+}
+
+// Die Tilgung ist wie eine Veräußerung zum Tilgungskurs zu behandeln.
+pub fn tilgung_berechnen(bestand: Bestand, nominale: Zahl, kurs: Zahl) -> Ergebnis {
+    let (bestand, _transaktion, steuer) =
+        verkauf_berechnen(bestand, nominale / 100, kurs, Zahl::zero());
+    (bestand, TransaktionsTyp::Tilgung { nominale, kurs }, steuer)
+}
+
+// Laut § 27 (2) 2. sind Zinsen Einkünfte aus der Überlassung von Kapital.
+pub fn kupon_berechnen(bestand: Bestand, betrag: Zahl) -> Ergebnis {
+    let steuer = SteuerDividende {
+        dividendenerträge_863: betrag,
+        ..Default::default()
+    };
+    (
+        bestand,
+        TransaktionsTyp::Kupon { betrag },
+        Steuer::Dividende(steuer),
+    )
+}
+
+// Laut § 27b (2) sind Entgelte für die Überlassung von Kryptowährungen laufende Einkünfte.
+// Die durch Staking erhaltenen Stück werden mit ihrem Wert als Anschaffungskosten eingebucht.
+pub fn staking_berechnen(bestand: Bestand, stück: Zahl, preis: Zahl) -> Ergebnis {
+    let (bestand, _transaktion, _steuer) = kauf_berechnen(bestand, stück, preis, Zahl::zero());
+    let steuer = SteuerKrypto {
+        laufende_einkünfte_171: runde(stück * preis, 2),
+        ..Default::default()
+    };
+    (
+        bestand,
+        TransaktionsTyp::Staking { stück, preis },
+        Steuer::Krypto(steuer),
+    )
+}
+
+// Laut § 27b (3) sind realisierte Wertsteigerungen von Kryptowährungen eigene Einkünfte,
+// welche getrennt von jenen aus Wertpapieren erklärt werden.
+pub fn krypto_steuer(steuer: Steuer) -> Steuer {
+    match steuer {
+        Steuer::Verkauf(verkauf) => Steuer::Krypto(SteuerKrypto {
+            überschüsse_172: verkauf.überschüsse_994,
+            verluste_173: verkauf.verluste_892,
+            gewinn_altbestand: verkauf.gewinn_altbestand,
+            ..Default::default()
+        }),
+        steuer => steuer,
+    }
+}
+
+// Laut § 27 (4) 1. sind Stillhalterprämien Einkünfte aus Derivaten, und sind bereits bei
+// Zufluss zu versteuern. Die Stillhalterposition wird als negativer Bestand geführt.
+pub fn stillhalter_berechnen(mut bestand: Bestand, stück: Zahl, preis: Zahl) -> Ergebnis {
+    bestand.stück -= stück;
+    let steuer = SteuerDerivat {
+        überschüsse_993: stück * preis,
+        ..Default::default()
+    };
+    (
+        bestand,
+        TransaktionsTyp::Stillhalterprämie { stück, preis },
+        Steuer::Derivat(steuer),
+    )
+}
+
+// Da die Stillhalterprämie bereits bei Zufluss versteuert wurde, ist der Preis für die
+// Glattstellung zur Gänze ein Verlust.
+pub fn glattstellung_berechnen(mut bestand: Bestand, stück: Zahl, preis: Zahl) -> Ergebnis {
+    bestand.stück += stück;
+    let steuer = SteuerDerivat {
+        verluste_896: stück * preis,
+        ..Default::default()
+    };
+    (
+        bestand,
+        TransaktionsTyp::Glattstellung { stück, preis },
+        Steuer::Derivat(steuer),
+    )
+}
+
+// Bei einer gekauften Option sind die Anschaffungskosten beim Verfall ein Verlust, bei einer
+// Stillhalterposition verbleibt die bereits versteuerte Prämie.
+pub fn verfall_berechnen(bestand: Bestand) -> Ergebnis {
+    let gekauft = bestand.stück > Zahl::zero();
+    let stück = if gekauft {
+        bestand.stück
+    } else {
+        -bestand.stück
+    };
+    let (bestand, steuer) = if gekauft {
+        let (bestand, _transaktion, steuer) = ausbuchung_berechnen(bestand, Zahl::zero());
+        (bestand, steuer)
+    } else {
+        (Bestand::default(), Steuer::Keine)
+    };
+    (bestand, TransaktionsTyp::Verfall { stück }, steuer)
+}
+
+/// Der Kauf oder Verkauf des Basiswertes aus der Ausübung einer Option.
+#[derive(Debug, Clone, Copy)]
+pub struct Andienung {
+    pub kauf: bool,
+    pub stück: Zahl,
+    pub preis: Zahl,
+}
+
+// Bei der Ausübung einer gekauften Option ist kein Gewinn zu realisieren, deren
+// Anschaffungskosten erhöhen bei einem Call die Anschaffungskosten, und mindern bei einem Put
+// den Veräußerungserlös des Basiswertes. Bei einer Stillhalterposition wurde die Prämie bereits
+// versteuert, der Basiswert wird daher zum Basispreis geliefert oder abgenommen.
+pub fn ausübung_berechnen(
+    mut bestand: Bestand,
+    stück: Zahl,
+    art: Optionsart,
+    basiswert: String,
+    basispreis: Zahl,
+) -> (Ergebnis, Andienung) {
+    let gekauft = bestand.stück > Zahl::zero();
+    let (kauf, andienungspreis) = if gekauft {
+        let abgang = abgang_berechnen(&mut bestand, stück);
+        let prämie = abgang.summe() / stück;
+        match art {
+            Optionsart::Call => (true, basispreis + prämie),
+            Optionsart::Put => (false, basispreis - prämie),
+        }
+    } else {
+        bestand.stück += stück;
+        (art == Optionsart::Put, basispreis)
+    };
+
+    (
+        (
+            bestand,
+            TransaktionsTyp::Ausübung {
+                stück,
+                art,
+                basiswert,
+                basispreis,
+            },
+            Steuer::Keine,
+        ),
+        Andienung {
+            kauf,
+            stück,
+            preis: andienungspreis,
+        },
+    )
+}
+
+// Laut § 27 (4) sind Einkünfte aus Derivaten eigene Einkünfte, welche getrennt von den
+// realisierten Wertsteigerungen erklärt werden.
+pub fn derivat_steuer(steuer: Steuer) -> Steuer {
+    match steuer {
+        Steuer::Verkauf(verkauf) => Steuer::Derivat(SteuerDerivat {
+            überschüsse_993: verkauf.überschüsse_994,
+            verluste_896: verkauf.verluste_892,
+            gewinn_altbestand: verkauf.gewinn_altbestand,
+        }),
+        steuer => steuer,
+    }
+}
+
+// Laut § 4 (12) gilt die Einlagenrückzahlung beim Anteilsinhaber als Veräußerung. Sie mindert
+// die Anschaffungskosten, nur der diese übersteigende Betrag ist ein Überschuss.
+pub fn einlagenrückzahlung_berechnen(mut bestand: Bestand, betrag: Zahl) -> Ergebnis {
+    let prostück = runde(betrag / bestand.stück, 4);
+
+    let korrektur = prostück.min(bestand.preis);
+    let überschuss = (prostück - korrektur) * bestand.neu_stück();
+    bestand.preis -= korrektur;
+
+    let alt_korrektur = prostück.min(bestand.alt.preis);
+    let gewinn_altbestand = (prostück - alt_korrektur) * bestand.alt.stück;
+    bestand.alt.preis -= alt_korrektur;
+
+    let steuer = if überschuss.is_zero() && gewinn_altbestand.is_zero() {
+        Steuer::Keine
+    } else {
+        Steuer::Verkauf(SteuerVerkauf {
+            überschüsse_994: runde(überschuss, 2),
+            gewinn_altbestand: runde(gewinn_altbestand, 2),
+            ..Default::default()
+        })
+    };
+
+    (
+        bestand,
+        TransaktionsTyp::Einlagenrückzahlung {
+            betrag,
+            korrektur: -korrektur,
+        },
+        steuer,
+    )
+}
+
 pub fn ausschüttung_berechnen(
     mut bestand: Bestand,
     auszahlung: Zahl,
@@ -196,6 +893,9 @@ pub fn meldung_berechnen(bestand: &mut Bestand, meldung: &FondMeldung) -> Steuer
         4,
     );
     bestand.preis += korrektur;
+    if !bestand.alt.stück.is_zero() {
+        bestand.alt.preis += korrektur;
+    }
 
     Steuer::Ausschüttung(steuer)
 }
@@ -205,65 +905,691 @@ pub fn meldung_berechnen(bestand: &mut Bestand, meldung: &FondMeldung) -> Steuer
 // Unterschiedsbetrages zwischen dem ersten und letzten im Kalenderjahr
 // festgesetzten Rücknahmepreis, mindestens jedoch in Höhe von 10 vH des
 // am Ende des Kalenderjahres festgesetzten Rücknahmepreises zu schätzen.
+//
+// Der Ausgangswert ist der erste Rücknahmepreis des Jahres für den Bestand zu Jahresbeginn,
+// zuzüglich der Anschaffungskosten der während des Jahres erworbenen Anteile.
 pub fn ausschüttungsgleiche_beträge_berechnen(
     stück: Zahl,
-    wert_anfang: Zahl,
-    wert_ende: Zahl,
+    ausgangswert: Zahl,
+    erster_kurs: Option<Zahl>,
+    letzter_kurs: Zahl,
 ) -> (Zahl, TransaktionsTyp, Steuer) {
     let mut steuer = SteuerAusschüttung::default();
 
-    dbg!(wert_anfang, wert_ende);
-    let unterschied = wert_ende - wert_anfang;
-    steuer.ausschüttungsgleiche_erträge_937 =
-        (unterschied * Zahl::new(90, 100)).max(wert_ende * Zahl::new(10, 100));
+    let wert_ende = stück * letzter_kurs;
+    let unterschied = wert_ende - ausgangswert;
+    steuer.ausschüttungsgleiche_erträge_937 = runde(
+        (unterschied * Zahl::new(90, 100)).max(wert_ende * Zahl::new(10, 100)),
+        2,
+    );
 
     // Erhöhung der Anschaffungskosten:
     let korrektur = runde(steuer.ausschüttungsgleiche_erträge_937 / stück, 4);
 
     (
         korrektur,
-        TransaktionsTyp::Ausschüttung {
-            brutto: Zahl::default(),
-            melde_id: None,
+        TransaktionsTyp::Schätzung {
+            erster_kurs,
+            letzter_kurs,
+            ausgangswert,
         },
         Steuer::Ausschüttung(steuer),
     )
 }
 
-fn runde(zahl: Zahl, stellen: u32) -> Zahl {
+// Für während des Jahres veräußerte Anteile gibt es keinen Rücknahmepreis zum Jahresende.
+// Für diese werden die ausschüttungsgleichen Erträge pauschal mit 6 vH des Veräußerungspreises
+// geschätzt, welche die Anschaffungskosten der veräußerten Anteile vor dem Verkauf erhöhen.
+pub fn verkauf_schätzung_berechnen(stück: Zahl, preis: Zahl) -> (Zahl, TransaktionsTyp, Steuer) {
+    let steuer = SteuerAusschüttung {
+        ausschüttungsgleiche_erträge_937: runde(stück * preis * Zahl::new(6, 100), 2),
+        ..Default::default()
+    };
+
+    let korrektur = runde(steuer.ausschüttungsgleiche_erträge_937 / stück, 4);
+
+    (
+        korrektur,
+        TransaktionsTyp::SchätzungVerkauf { stück, preis },
+        Steuer::Ausschüttung(steuer),
+    )
+}
+
+pub(crate) fn runde(zahl: Zahl, stellen: u32) -> Zahl {
     let faktor = 10_i64.pow(stellen);
     (zahl * faktor).round() / faktor
 }
 
+/// Ein Schritt des Verlustausgleichs, bei dem Verluste mit den Einkünften einer Kennzahl
+/// ausgeglichen werden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ausgleich {
+    pub kennzahl: &'static str,
+    pub einkünfte: Zahl,
+    pub ausgeglichen: Zahl,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verlustausgleich {
+    /// Summe der Verluste (892, 896, 173)
+    pub verluste: Zahl,
+    /// Die Schritte des Ausgleichs, in der Reihenfolge in der sie angewandt werden
+    pub schritte: Vec<Ausgleich>,
+    /// Nicht ausgeglichene Verluste, welche laut § 27 (8) nicht vorgetragen werden können
+    pub verfallen: Zahl,
+    /// Einkünfte nach dem Verlustausgleich
+    pub bemessungsgrundlage: Zahl,
+    pub steuer: Zahl,
+    /// Die österreichische Steuer auf die Einkünfte mit Quellensteuer nach dem Ausgleich
+    pub höchstbetrag_998: Zahl,
+    pub angerechnete_quellensteuer: Zahl,
+    pub nachzahlung: Zahl,
+}
+
 impl SteuerJahr {
+    // Der Sondersteuersatz laut § 27a (1) beträgt seit 2016 27,5%, davor 25%.
+    // TODO: Einkünfte mit 25% seit 2016 (Zinsen aus Geldeinlagen bei Kreditinstituten) unter
+    // eigenen Kennzahlen, bisher gilt ein einheitlicher Steuersatz je Jahr.
+    pub fn steuersatz(&self) -> Zahl {
+        if self.jahr < 2016 {
+            Zahl::new(25, 100)
+        } else {
+            Zahl::new(275, 1000)
+        }
+    }
+
+    // Laut § 27 (8) können Verluste mit Überschüssen und laufenden Einkünften ausgeglichen werden,
+    // nicht aber mit Zinsen aus Geldeinlagen bei Kreditinstituten. Diese werden hier nicht erfasst,
+    // da sie bereits von der Bank endbesteuert sind.
+    // Die Verluste werden zuerst mit den Überschüssen, dann mit den laufenden Einkünften ohne
+    // Quellensteuer, und erst zuletzt mit Dividenden und Ausschüttungen ausgeglichen, damit
+    // möglichst viel der ausländischen Quellensteuer anrechenbar bleibt. Ein Verlustvortrag
+    // ist nicht möglich.
+    pub fn verlustausgleich(&self) -> Verlustausgleich {
+        let verluste = self.verluste_891
+            + self.verluste_892
+            + self.derivate_verluste_893
+            + self.derivate_verluste_896
+            + self.krypto_verluste_176
+            + self.krypto_verluste_173;
+        let einkünfte = [
+            (
+                "Überschüsse aus realisierten Wertsteigerungen (981)",
+                self.überschüsse_981,
+            ),
+            (
+                "Überschüsse aus realisierten Wertsteigerungen (994)",
+                self.überschüsse_994,
+            ),
+            (
+                "Überschüsse aus Derivaten (982)",
+                self.derivate_überschüsse_982,
+            ),
+            (
+                "Überschüsse aus Derivaten (993)",
+                self.derivate_überschüsse_993,
+            ),
+            (
+                "Überschüsse aus Kryptowährungen (175)",
+                self.krypto_überschüsse_175,
+            ),
+            (
+                "Überschüsse aus Kryptowährungen (172)",
+                self.krypto_überschüsse_172,
+            ),
+            (
+                "Laufende Einkünfte aus Kryptowährungen (174)",
+                self.krypto_laufende_einkünfte_174,
+            ),
+            (
+                "Laufende Einkünfte aus Kryptowährungen (171)",
+                self.krypto_laufende_einkünfte_171,
+            ),
+            (
+                "Ausschüttungsgleiche Erträge (936)",
+                self.ausschüttungsgleiche_erträge_936,
+            ),
+            ("Ausschüttungen (897)", self.ausschüttungen_897),
+            ("Einkünfte aus Dividenden (862)", self.dividendenerträge_862),
+            (
+                "Ausschüttungsgleiche Erträge (937)",
+                self.ausschüttungsgleiche_erträge_937,
+            ),
+            ("Ausschüttungen (898)", self.ausschüttungen_898),
+            ("Einkünfte aus Dividenden (863)", self.dividendenerträge_863),
+        ];
+        // Die letzten sechs sind die Dividenden und Ausschüttungen, auf welche Quellensteuer
+        // anfallen kann.
+        const MIT_QUELLENSTEUER: usize = 6;
+
+        let mut offen = verluste;
+        let mut schritte = vec![];
+        let mut rest = [Zahl::zero(); 14];
+        for (i, (kennzahl, einkünfte)) in einkünfte.into_iter().enumerate() {
+            let ausgeglichen = offen.min(einkünfte).max(Zahl::zero());
+            offen -= ausgeglichen;
+            rest[i] = einkünfte - ausgeglichen;
+            if !ausgeglichen.is_zero() {
+                schritte.push(Ausgleich {
+                    kennzahl,
+                    einkünfte,
+                    ausgeglichen,
+                });
+            }
+        }
+
+        let steuersatz = self.steuersatz();
+        let bemessungsgrundlage = rest.iter().sum::<Zahl>();
+        let steuer = runde(bemessungsgrundlage * steuersatz, 2);
+
+        // Die Quellensteuer ist höchstens bis zur österreichischen Steuer auf die Einkünfte
+        // anrechenbar, welche nach dem Ausgleich verbleiben.
+        let ausländisch = rest[rest.len() - MIT_QUELLENSTEUER..].iter().sum::<Zahl>();
+        let höchstbetrag_998 = runde(ausländisch * steuersatz, 2);
+        let angerechnete_quellensteuer = self.anrechenbare_quellensteuer_998.min(höchstbetrag_998);
+
+        Verlustausgleich {
+            verluste,
+            schritte,
+            verfallen: offen,
+            bemessungsgrundlage,
+            steuer,
+            höchstbetrag_998,
+            angerechnete_quellensteuer,
+            nachzahlung: steuer - self.gezahlte_inländische_kest_899 - angerechnete_quellensteuer,
+        }
+    }
+
     pub fn berechne_nachzahlung(&self) -> Zahl {
-        let zu_versteuern = self.überschüsse_994 - self.verluste_892
-            + self.dividendenerträge_863
-            + self.ausschüttungen_898
-            + self.ausschüttungsgleiche_erträge_937;
-        let steuer = zu_versteuern * Zahl::new(275, 1000);
-        steuer - self.gezahlte_inländische_kest_899 - self.anrechenbare_quellensteuer_998
+        self.verlustausgleich().nachzahlung
     }
-}
 
-impl AddAssign<Steuer> for SteuerJahr {
-    fn add_assign(&mut self, steuer: Steuer) {
-        match steuer {
-            Steuer::Keine => {}
-            Steuer::Verkauf(s) => {
+    /// Fügt die Steuer einer Transaktion hinzu, bei einem inländischen Depot unter den Kennzahlen
+    /// für Einkünfte mit KeSt-Abzug.
+    pub fn hinzufügen(&mut self, steuer: Steuer, inland: bool) {
+        match (steuer, inland) {
+            (Steuer::Keine, _) => {}
+            (Steuer::Verkauf(s), false) => {
                 self.überschüsse_994 += s.überschüsse_994;
                 self.verluste_892 += s.verluste_892;
             }
-            Steuer::Dividende(s) => {
-                self.dividendenerträge_863 += s.dividendenerträge_863;
+            (Steuer::Verkauf(s), true) => {
+                self.überschüsse_981 += s.überschüsse_994;
+                self.verluste_891 += s.verluste_892;
+            }
+            // Bei inländischen Depots rechnet die Bank die Quellensteuer bereits auf die KeSt an,
+            // diese wird als 998 und die einbehaltene KeSt laut Bescheinigung als 899 erklärt.
+            (Steuer::Dividende(s), _) => {
+                if inland {
+                    self.dividendenerträge_862 += s.dividendenerträge_863;
+                } else {
+                    self.dividendenerträge_863 += s.dividendenerträge_863;
+                }
                 self.gezahlte_inländische_kest_899 += s.gezahlte_inländische_kest_899;
                 self.anrechenbare_quellensteuer_998 += s.anrechenbare_quellensteuer_998;
             }
-            Steuer::Ausschüttung(s) => {
-                self.ausschüttungen_898 += s.ausschüttungen_898;
-                self.ausschüttungsgleiche_erträge_937 += s.ausschüttungsgleiche_erträge_937;
+            (Steuer::Ausschüttung(s), _) => {
+                if inland {
+                    self.ausschüttungen_897 += s.ausschüttungen_898;
+                    self.ausschüttungsgleiche_erträge_936 += s.ausschüttungsgleiche_erträge_937;
+                } else {
+                    self.ausschüttungen_898 += s.ausschüttungen_898;
+                    self.ausschüttungsgleiche_erträge_937 += s.ausschüttungsgleiche_erträge_937;
+                }
                 self.anrechenbare_quellensteuer_998 += s.anrechenbare_quellensteuer_998;
             }
+            (Steuer::Krypto(s), false) => {
+                self.krypto_laufende_einkünfte_171 += s.laufende_einkünfte_171;
+                self.krypto_überschüsse_172 += s.überschüsse_172;
+                self.krypto_verluste_173 += s.verluste_173;
+            }
+            (Steuer::Krypto(s), true) => {
+                self.krypto_laufende_einkünfte_174 += s.laufende_einkünfte_171;
+                self.krypto_überschüsse_175 += s.überschüsse_172;
+                self.krypto_verluste_176 += s.verluste_173;
+            }
+            (Steuer::Derivat(s), false) => {
+                self.derivate_überschüsse_993 += s.überschüsse_993;
+                self.derivate_verluste_896 += s.verluste_896;
+            }
+            (Steuer::Derivat(s), true) => {
+                self.derivate_überschüsse_982 += s.überschüsse_993;
+                self.derivate_verluste_893 += s.verluste_896;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dba::{quellensteuersatz, Land};
+
+    #[test]
+    fn verlustausgleich_schont_quellensteuer() {
+        let jahr = SteuerJahr {
+            überschüsse_994: 300.into(),
+            verluste_892: 500.into(),
+            derivate_verluste_896: 100.into(),
+            ausschüttungsgleiche_erträge_937: 100.into(),
+            dividendenerträge_863: 1000.into(),
+            anrechenbare_quellensteuer_998: 150.into(),
+            ..SteuerJahr::new(2023)
+        };
+        let ausgleich = jahr.verlustausgleich();
+        assert_eq!(ausgleich.verluste, Zahl::from(600));
+        let ausgeglichen: Vec<_> = ausgleich.schritte.iter().map(|s| s.ausgeglichen).collect();
+        assert_eq!(ausgeglichen, [300.into(), 100.into(), 200.into()]);
+        assert!(ausgleich.verfallen.is_zero());
+        assert_eq!(ausgleich.bemessungsgrundlage, Zahl::from(800));
+        assert_eq!(ausgleich.steuer, Zahl::from(220));
+        assert_eq!(ausgleich.höchstbetrag_998, Zahl::from(220));
+        assert_eq!(ausgleich.nachzahlung, Zahl::from(70));
+
+        // Übersteigen die Verluste alle Einkünfte, verfallen sie, und es ist keine
+        // Quellensteuer mehr anrechenbar.
+        let jahr = SteuerJahr {
+            verluste_892: 1400.into(),
+            ..jahr
+        };
+        let ausgleich = jahr.verlustausgleich();
+        assert_eq!(ausgleich.verfallen, Zahl::from(100));
+        assert!(ausgleich.bemessungsgrundlage.is_zero());
+        assert!(ausgleich.angerechnete_quellensteuer.is_zero());
+        assert!(ausgleich.nachzahlung.is_zero());
+    }
+
+    #[test]
+    fn kennzahlen_nach_inland_und_ausland() {
+        let verkauf = Steuer::Verkauf(SteuerVerkauf {
+            überschüsse_994: 100.into(),
+            ..Default::default()
+        });
+        let mut jahr = SteuerJahr::new(2015);
+        jahr.hinzufügen(verkauf, true);
+        jahr.hinzufügen(verkauf, false);
+        assert_eq!(jahr.überschüsse_981, Zahl::from(100));
+        assert_eq!(jahr.überschüsse_994, Zahl::from(100));
+        // Die laut Bescheinigung einbehaltene KeSt des inländischen Depots wird angerechnet
+        jahr.gezahlte_inländische_kest_899 += Zahl::from(25);
+        let ausgleich = jahr.verlustausgleich();
+        assert_eq!(ausgleich.steuer, Zahl::from(50));
+        assert_eq!(ausgleich.nachzahlung, Zahl::from(25));
+    }
+
+    #[test]
+    fn inländische_kest_angerechnet() {
+        let gewinn = Steuer::Verkauf(SteuerVerkauf {
+            überschüsse_994: 100.into(),
+            ..Default::default()
+        });
+        let verlust = Steuer::Verkauf(SteuerVerkauf {
+            verluste_892: 100.into(),
+            ..Default::default()
+        });
+        let dividende = Steuer::Dividende(SteuerDividende {
+            dividendenerträge_863: 100.into(),
+            anrechenbare_quellensteuer_998: 15.into(),
+            ..Default::default()
+        });
+
+        // Die Bank behält auf die Dividende nur 27,5% abzüglich der Quellensteuer ein
+        let mut jahr = SteuerJahr::new(2023);
+        jahr.hinzufügen(gewinn, true);
+        jahr.hinzufügen(dividende, true);
+        jahr.gezahlte_inländische_kest_899 += Zahl::new(2750 + 1250, 100);
+        assert_eq!(jahr.dividendenerträge_862, Zahl::from(100));
+        assert_eq!(jahr.anrechenbare_quellensteuer_998, Zahl::from(15));
+        assert_eq!(jahr.berechne_nachzahlung(), Zahl::zero());
+
+        // Der Verlust im ausländischen Depot gleicht den Gewinn im inländischen aus
+        jahr.hinzufügen(verlust, false);
+        let ausgleich = jahr.verlustausgleich();
+        assert_eq!(ausgleich.angerechnete_quellensteuer, Zahl::from(15));
+        assert_eq!(ausgleich.nachzahlung, Zahl::new(-275, 10));
+    }
+
+    #[test]
+    fn spesen_nicht_abzugsfähig() {
+        let bestand = Bestand::default();
+        let (bestand, typ, _) = kauf_berechnen(bestand, 10.into(), 100.into(), 5.into());
+        assert_eq!(bestand.preis, Zahl::from(100));
+        assert_eq!(typ.geldfluss(), Zahl::from(-1005));
+
+        let (bestand, typ, steuer) = verkauf_berechnen(bestand, 10.into(), 110.into(), 5.into());
+        assert!(bestand.stück.is_zero());
+        assert_eq!(typ.geldfluss(), Zahl::from(1095));
+        let Steuer::Verkauf(steuer) = steuer else {
+            panic!("Verkauf sollte besteuert werden");
+        };
+        assert_eq!(steuer.überschüsse_994, Zahl::from(100));
+    }
+
+    #[test]
+    fn altbestand_zuerst_veräußert() {
+        let bestand = Bestand::default();
+        let (bestand, _, _) = kauf_altbestand_berechnen(bestand, 10.into(), 50.into(), 0.into());
+        let (bestand, _, _) = kauf_berechnen(bestand, 10.into(), 100.into(), 0.into());
+        assert_eq!(bestand.stück, Zahl::from(20));
+        assert_eq!(bestand.preis, Zahl::from(100));
+        assert_eq!(bestand.alt.preis, Zahl::from(50));
+
+        let (bestand, _, steuer) = verkauf_berechnen(bestand, 15.into(), 120.into(), 0.into());
+        let Steuer::Verkauf(steuer) = steuer else {
+            panic!("Verkauf sollte besteuert werden");
+        };
+        assert_eq!(steuer.gewinn_altbestand, Zahl::from(700));
+        assert_eq!(steuer.überschüsse_994, Zahl::from(100));
+        assert_eq!(bestand.stück, Zahl::from(5));
+        assert!(bestand.alt.stück.is_zero());
+        assert!(bestand.alt.preis.is_zero());
+        assert_eq!(bestand.preis, Zahl::from(100));
+    }
+
+    #[test]
+    fn depotübertrag() {
+        let quelle = Bestand {
+            stück: 10.into(),
+            preis: 100.into(),
+            ..Default::default()
+        };
+        let ((quelle, _, _), übertrag) = depotausgang_berechnen(quelle, 4.into(), "B".into());
+        assert_eq!(quelle.stück, Zahl::from(6));
+        assert_eq!(quelle.preis, Zahl::from(100));
+
+        let ziel = Bestand {
+            stück: 4.into(),
+            preis: 50.into(),
+            ..Default::default()
+        };
+        let (ziel, _, steuer) = depoteingang_berechnen(ziel, übertrag, "A".into());
+        assert!(matches!(steuer, Steuer::Keine));
+        assert_eq!(ziel.stück, Zahl::from(8));
+        assert_eq!(ziel.preis, Zahl::from(75));
+    }
+
+    #[test]
+    fn unentgeltlicher_erwerb_mit_anschaffung_des_rechtsvorgängers() {
+        let bestand = Bestand {
+            stück: 10.into(),
+            preis: 100.into(),
+            ..Default::default()
+        };
+        // vor 2011 vom Rechtsvorgänger angeschafft, gehört daher zum Altbestand
+        let anschaffung = "2008-05-01".parse().unwrap();
+        let (bestand, typ, steuer) =
+            unentgeltlicher_erwerb_berechnen(bestand, 5.into(), 40.into(), anschaffung, true);
+        assert!(matches!(steuer, Steuer::Keine));
+        assert!(matches!(
+            typ,
+            TransaktionsTyp::UnentgeltlicherErwerb { anschaffung: a, .. } if a == anschaffung
+        ));
+        assert_eq!(bestand.stück, Zahl::from(15));
+        assert_eq!(bestand.preis, Zahl::from(100));
+        assert_eq!(bestand.alt.stück, Zahl::from(5));
+        assert_eq!(bestand.alt.preis, Zahl::from(40));
+
+        // danach angeschaffte Stück gehen in den Durchschnittspreis des Neubestandes ein
+        let anschaffung = "2015-05-01".parse().unwrap();
+        let (bestand, _, _) =
+            unentgeltlicher_erwerb_berechnen(bestand, 10.into(), 50.into(), anschaffung, false);
+        assert_eq!(bestand.stück, Zahl::from(25));
+        assert_eq!(bestand.preis, Zahl::from(75));
+        assert_eq!(bestand.alt.stück, Zahl::from(5));
+    }
+
+    #[test]
+    fn schenkung_ohne_besteuerung() {
+        let bestand = Bestand {
+            stück: 15.into(),
+            preis: 100.into(),
+            alt: Altbestand {
+                stück: 5.into(),
+                preis: 40.into(),
+            },
+            ..Default::default()
+        };
+        // wie bei einem Verkauf scheidet zuerst der Altbestand aus
+        let (bestand, _, steuer) = schenkung_berechnen(bestand, 8.into());
+        assert!(matches!(steuer, Steuer::Keine));
+        assert_eq!(bestand.stück, Zahl::from(7));
+        assert!(bestand.alt.stück.is_zero());
+        assert!(bestand.alt.preis.is_zero());
+        assert_eq!(bestand.preis, Zahl::from(100));
+
+        let (bestand, _, _) = schenkung_berechnen(bestand, 7.into());
+        assert!(bestand.stück.is_zero());
+        assert!(bestand.preis.is_zero());
+    }
+
+    #[test]
+    fn bezugsrechte() {
+        let bestand = Bestand {
+            stück: 10.into(),
+            preis: 100.into(),
+            ..Default::default()
+        };
+        let (bestand, _, steuer) = bezugsrecht_berechnen(bestand, 10.into());
+        assert!(matches!(steuer, Steuer::Keine));
+        assert_eq!(bestand.bezugsrechte, Zahl::from(10));
+        assert_eq!(bestand.preis, Zahl::from(100));
+
+        let (bestand, _, steuer) = bezugsrecht_verkauf_berechnen(bestand, 2.into(), 3.into());
+        let Steuer::Verkauf(steuer) = steuer else {
+            panic!("Verkauf erwartet");
+        };
+        assert_eq!(steuer.überschüsse_994, Zahl::from(6));
+        assert_eq!(bestand.bezugsrechte, Zahl::from(8));
+
+        let (bestand, _, _) =
+            bezugsrecht_ausübung_berechnen(bestand, 8.into(), 2.into(), 40.into());
+        assert!(bestand.bezugsrechte.is_zero());
+        assert_eq!(bestand.stück, Zahl::from(12));
+        assert_eq!(bestand.preis, Zahl::from(90));
+    }
+
+    #[test]
+    fn umtausch_mit_zuzahlung() {
+        let bestand = Bestand {
+            stück: 10.into(),
+            preis: 30.into(),
+            ..Default::default()
+        };
+        let ((bestand, _, steuer), übertrag) =
+            umtausch_berechnen(bestand, 10.into(), "B".into(), 5.into(), 20.into());
+        assert!(bestand.stück.is_zero());
+        let Steuer::Verkauf(steuer) = steuer else {
+            panic!("Verkauf erwartet");
+        };
+        assert_eq!(steuer.überschüsse_994, Zahl::from(20));
+        assert_eq!(übertrag.stück, Zahl::from(5));
+        assert_eq!(übertrag.summe(), Zahl::from(300));
+
+        let ziel = Bestand::default();
+        let (ziel, _, _) = umtauscheingang_berechnen(ziel, übertrag, "A".into());
+        assert_eq!(ziel.stück, Zahl::from(5));
+        assert_eq!(ziel.preis, Zahl::from(60));
+    }
+
+    #[test]
+    fn gratisaktien_und_aktiendividende() {
+        let bestand = Bestand {
+            stück: 10.into(),
+            preis: 110.into(),
+            ..Default::default()
+        };
+        let (bestand, _, steuer) = gratisaktien_berechnen(bestand, 1.into());
+        assert!(matches!(steuer, Steuer::Keine));
+        assert_eq!(bestand.stück, Zahl::from(11));
+        assert_eq!(bestand.summe(), Zahl::from(1100));
+
+        let satz = quellensteuersatz(Land::new("US").unwrap(), None, 2023);
+        let (bestand, _, steuer) =
+            aktiendividende_berechnen(bestand, satz, 1.into(), 100.into(), 15.into());
+        let Steuer::Dividende(steuer) = steuer else {
+            panic!("Dividende erwartet");
+        };
+        assert_eq!(steuer.dividendenerträge_863, Zahl::from(100));
+        assert_eq!(steuer.anrechenbare_quellensteuer_998, Zahl::from(15));
+        assert_eq!(bestand.stück, Zahl::from(12));
+        assert_eq!(bestand.summe(), Zahl::from(1200));
+    }
+
+    #[test]
+    fn einlagenrückzahlung_mindert_anschaffungskosten() {
+        let bestand = Bestand {
+            stück: 10.into(),
+            preis: 5.into(),
+            ..Default::default()
+        };
+        let (bestand, _, steuer) = einlagenrückzahlung_berechnen(bestand, 30.into());
+        assert!(matches!(steuer, Steuer::Keine));
+        assert_eq!(bestand.preis, Zahl::from(2));
+
+        let (bestand, _, steuer) = einlagenrückzahlung_berechnen(bestand, 50.into());
+        let Steuer::Verkauf(steuer) = steuer else {
+            panic!("Verkauf erwartet");
+        };
+        assert_eq!(steuer.überschüsse_994, Zahl::from(30));
+        assert!(bestand.preis.is_zero());
+
+        // die Korrektur je Stück wird wie bei Meldungen auf 4 Stellen gerundet
+        let bestand = Bestand {
+            stück: 3.into(),
+            preis: 5.into(),
+            ..Default::default()
+        };
+        let (bestand, _, _) = einlagenrückzahlung_berechnen(bestand, 1.into());
+        assert_eq!(bestand.preis, Zahl::new(46667, 10000));
+    }
+
+    #[test]
+    fn ausbuchung_ohne_abfindung() {
+        let bestand = Bestand {
+            stück: 10.into(),
+            preis: 5.into(),
+            ..Default::default()
+        };
+        let (bestand, _, steuer) = ausbuchung_berechnen(bestand, Zahl::zero());
+        let Steuer::Verkauf(steuer) = steuer else {
+            panic!("Verkauf erwartet");
+        };
+        assert_eq!(steuer.verluste_892, Zahl::from(50));
+        assert!(bestand.stück.is_zero());
+    }
+
+    #[test]
+    fn anleihe_mit_stückzinsen() {
+        let kauf = anleihe_kauf_berechnen(
+            Bestand::default(),
+            10_000.into(),
+            Zahl::new(985, 10),
+            120.into(),
+            Zahl::zero(),
+            false,
+        );
+        let (bestand, _, _) = kauf;
+        // in Einheiten zu 100 Nominale, samt den gezahlten Stückzinsen
+        assert_eq!(bestand.stück, Zahl::from(100));
+        assert_eq!(bestand.preis, Zahl::new(997, 10));
+
+        let (bestand, _, steuer) =
+            anleihe_verkauf_berechnen(bestand, 5_000.into(), 101.into(), 50.into(), Zahl::zero());
+        let Steuer::Verkauf(steuer) = steuer else {
+            panic!("Verkauf erwartet");
+        };
+        assert_eq!(steuer.überschüsse_994, Zahl::from(115));
+
+        let (_, _, steuer) = kupon_berechnen(bestand, 150.into());
+        let Steuer::Dividende(steuer) = steuer else {
+            panic!("Dividende erwartet");
+        };
+        assert_eq!(steuer.dividendenerträge_863, Zahl::from(150));
+
+        let (bestand, _, steuer) = tilgung_berechnen(bestand, 5_000.into(), 100.into());
+        let Steuer::Verkauf(steuer) = steuer else {
+            panic!("Verkauf erwartet");
+        };
+        assert_eq!(steuer.überschüsse_994, Zahl::from(15));
+        assert!(bestand.stück.is_zero());
+    }
+
+    #[test]
+    fn krypto_staking_und_verkauf() {
+        let bestand = Bestand::default();
+        let (bestand, _, steuer) = staking_berechnen(bestand, Zahl::new(1, 2), 2_000.into());
+        let Steuer::Krypto(steuer) = steuer else {
+            panic!("Krypto erwartet");
+        };
+        assert_eq!(steuer.laufende_einkünfte_171, Zahl::from(1_000));
+        assert_eq!(bestand.preis, Zahl::from(2_000));
+
+        let (_, _, steuer) = verkauf_berechnen(bestand, Zahl::new(1, 2), 1_800.into(), 0.into());
+        let Steuer::Krypto(steuer) = krypto_steuer(steuer) else {
+            panic!("Krypto erwartet");
+        };
+        assert_eq!(steuer.verluste_173, Zahl::from(100));
+        assert!(steuer.überschüsse_172.is_zero());
+    }
+
+    #[test]
+    fn stillhalter_und_ausübung() {
+        // Stillhalterprämie wird bei Zufluss versteuert
+        let (bestand, _, steuer) = stillhalter_berechnen(Bestand::default(), 100.into(), 2.into());
+        let Steuer::Derivat(steuer) = steuer else {
+            panic!("Derivat erwartet");
+        };
+        assert_eq!(steuer.überschüsse_993, Zahl::from(200));
+        assert_eq!(bestand.stück, Zahl::from(-100));
+
+        // Andienung eines Calls zum Basispreis
+        let ((bestand, _, _), andienung) =
+            ausübung_berechnen(bestand, 100.into(), Optionsart::Call, "A".into(), 50.into());
+        assert!(bestand.stück.is_zero());
+        assert!(!andienung.kauf);
+        assert_eq!(andienung.preis, Zahl::from(50));
+
+        // gekaufter Put mindert den Veräußerungserlös um die Prämie
+        let (bestand, _, _) = kauf_berechnen(Bestand::default(), 100.into(), 3.into(), 0.into());
+        let ((bestand, _, _), andienung) =
+            ausübung_berechnen(bestand, 100.into(), Optionsart::Put, "A".into(), 40.into());
+        assert!(bestand.stück.is_zero());
+        assert!(!andienung.kauf);
+        assert_eq!(andienung.preis, Zahl::from(37));
+
+        // gekaufte Option verfällt wertlos
+        let (bestand, _, _) = kauf_berechnen(Bestand::default(), 100.into(), 3.into(), 0.into());
+        let (_, _, steuer) = verfall_berechnen(bestand);
+        let Steuer::Derivat(steuer) = derivat_steuer(steuer) else {
+            panic!("Derivat erwartet");
+        };
+        assert_eq!(steuer.verluste_896, Zahl::from(300));
+    }
+
+    fn wert(wert: i64) -> Verkehrswert {
+        Verkehrswert {
+            wert: wert.into(),
+            quelle: Kursquelle::Angegeben,
+            fremdwährung: None,
         }
     }
+
+    #[test]
+    fn ausgliederung_teilt_anschaffungskosten() {
+        let bestand = Bestand {
+            stück: 100.into(),
+            preis: 100.into(),
+            ..Default::default()
+        };
+        let faktor = Zahl::new(1, 10);
+        let ((bestand, _, _), abgespalten) =
+            ausgliederung_berechnen(bestand, faktor, "B".into(), wert(40), wert(100));
+        assert_eq!(bestand.preis, Zahl::from(80));
+        assert_eq!(abgespalten.stück, Zahl::from(10));
+
+        let (ziel, _, steuer) = einbuchung_ausgliederung_berechnen(Bestand::default(), abgespalten);
+        assert!(matches!(steuer, Steuer::Keine));
+        assert_eq!(ziel.preis, Zahl::from(200));
+        assert_eq!(bestand.summe() + ziel.summe(), Zahl::from(10_000));
+    }
 }
@@ -2,9 +2,10 @@ use std::fmt;
 
 use num_traits::Zero;
 
-use crate::formatierung::Eur;
-use crate::{Bestand, Datum, Jahr, TransaktionsTyp, Wertpapier};
-use crate::{Steuer, SteuerAusschüttung, SteuerDividende, SteuerVerkauf};
+use crate::formatierung::{Dezimal, Eur};
+use crate::SteuerVerkauf;
+use crate::{Bestand, Datum, Jahr, Kursquelle, TransaktionsTyp, Wertpapier, Zahl};
+use crate::{Steuer, SteuerAusschüttung, SteuerDerivat, SteuerDividende, SteuerKrypto};
 
 pub struct TsvTitel;
 impl fmt::Display for TsvTitel {
@@ -24,17 +25,33 @@ impl fmt::Display for TsvWertpapier<'_> {
 }
 
 pub fn schreibe_titel<W: fmt::Write>(w: &mut W) -> fmt::Result {
-    write!(w, "Name\tISIN\tArt\tDatum\tBestand\tDurchschnittspreis\t")?; // 6
-    write!(w, "Aktion\tStück\tPreis\tBrutto\tAuszahlung\tMelde-ID\t")?; // 6
-    write!(w, "Überschuss (994)\tVerlust (892)\t")?; // 2
+    write!(
+        w,
+        "Name\tISIN\tArt\tDepot\tDatum\tBestand\tDurchschnittspreis\t"
+    )?; // 7
+    write!(
+        w,
+        "Altbestand\tDurchschnittspreis Altbestand\tBezugsrechte\t"
+    )?; // 3
+    write!(
+        w,
+        "Aktion\tStück\tPreis\tSpesen\tBrutto\tAuszahlung\tMelde-ID\t"
+    )?; // 7
+    write!(w, "Währung\tUmrechnungskurs\t")?; // 2
+    write!(w, "Überschuss (994)\tVerlust (892)\tGewinn Altbestand\t")?; // 3
     write!(
         w,
         "Dividendenertrag (863)\tGezahlte KeSt (899)\tAnrechenbare Quellensteuer (998)\t"
     )?; // 3
-    writeln!(
+    write!(
+        w,
+        "Ausschüttung (898)\tAusschüttungsgl Ertrag (937)\tAnrechenbare Quellensteuer (998)\t"
+    )?; // 3
+    write!(
         w,
-        "Ausschüttung (898)\tAusschüttungsgl Ertrag (937)\tAnrechenbare Quellensteuer (998)"
-    ) // 3
+        "Krypto laufend (171)\tKrypto Überschuss (172)\tKrypto Verlust (173)\t"
+    )?; // 3
+    writeln!(w, "Derivate Überschuss (993)\tDerivate Verlust (896)") // 2
 }
 
 pub fn schreibe_tsv<W: fmt::Write>(
@@ -49,61 +66,250 @@ pub fn schreibe_tsv<W: fmt::Write>(
         schreibe_anfang(w, wertpapier, transaktion.datum, transaktion.bestand)?;
 
         match &transaktion.typ {
-            TransaktionsTyp::Kauf { stück, preis } => {
-                write!(w, "Kauf\t{}\t{}\t\t\t\t", stück, Eur(*preis, 4))?;
+            TransaktionsTyp::Kauf {
+                stück,
+                preis,
+                spesen,
+            } => {
+                write!(w, "Kauf\t{}\t{}\t", stück, Eur(*preis, 4))?;
+                schreibe_spesen(w, *spesen)?;
+                write!(w, "\t\t\t")?;
             }
-            TransaktionsTyp::Verkauf { stück, preis } => {
-                write!(w, "Verkauf\t{}\t{}\t\t\t\t", stück, Eur(*preis, 4))?;
+            TransaktionsTyp::Verkauf {
+                stück,
+                preis,
+                spesen,
+            } => {
+                write!(w, "Verkauf\t{}\t{}\t", stück, Eur(*preis, 4))?;
+                schreibe_spesen(w, *spesen)?;
+                write!(w, "\t\t\t")?;
+            }
+
+            TransaktionsTyp::AnleiheKauf {
+                nominale,
+                kurs,
+                stückzinsen,
+                spesen,
+            } => {
+                write!(w, "Kauf\t{}\t{}\t", nominale, Dezimal(*kurs, 4))?;
+                schreibe_spesen(w, *spesen)?;
+                write!(w, "{}\t\t\t", Eur(*stückzinsen, 2))?;
+            }
+            TransaktionsTyp::AnleiheVerkauf {
+                nominale,
+                kurs,
+                stückzinsen,
+                spesen,
+            } => {
+                write!(w, "Verkauf\t{}\t{}\t", nominale, Dezimal(*kurs, 4))?;
+                schreibe_spesen(w, *spesen)?;
+                write!(w, "{}\t\t\t", Eur(*stückzinsen, 2))?;
+            }
+            TransaktionsTyp::Tilgung { nominale, kurs } => {
+                write!(w, "Tilgung\t{}\t{}\t\t\t\t\t", nominale, Dezimal(*kurs, 4))?;
             }
 
             TransaktionsTyp::Split { faktor } => {
-                write!(w, "Split\t{}\t\t\t\t\t", faktor)?;
+                write!(w, "Split\t{}\t\t\t\t\t\t", faktor)?;
             }
-            TransaktionsTyp::Ausgliederung { faktor, .. } => {
-                write!(w, "Ausgliederung\t{}\t\t\t\t\t", faktor)?;
+            TransaktionsTyp::Fondsverschmelzung { faktor, .. } => {
+                write!(w, "Fondsverschmelzung\t{}\t\t\t\t\t\t", faktor)?;
+            }
+            TransaktionsTyp::Gratisaktien { stück } => {
+                write!(w, "Gratisaktien\t{}\t\t\t\t\t\t", stück)?;
             }
-            TransaktionsTyp::Einbuchung { stück, preis } => {
+            TransaktionsTyp::Aktiendividende {
+                stück,
+                preis,
+                quellensteuer,
+                ..
+            } => {
+                let brutto = stück * preis;
                 write!(
                     w,
-                    "Einbuchung nach Ausgliederung\t{}\t{}\t\t\t\t",
+                    "Aktiendividende\t{}\t{}\t\t{}\t{}\t\t",
                     stück,
-                    Eur(*preis, 4)
+                    Eur(*preis, 4),
+                    Eur(brutto, 2),
+                    Eur(brutto - quellensteuer, 2)
                 )?;
             }
+            TransaktionsTyp::Ausgliederung { faktor, .. } => {
+                write!(w, "Ausgliederung\t{}\t\t\t\t\t\t", faktor)?;
+            }
+            TransaktionsTyp::Einbuchung {
+                stück,
+                preis,
+                quelle,
+            } => {
+                let aktion = if *quelle == Kursquelle::Ausgliederung {
+                    "Einbuchung nach Ausgliederung"
+                } else {
+                    "Einbuchung"
+                };
+                write!(w, "{}\t{}\t{}\t\t\t\t\t", aktion, stück, Eur(*preis, 4))?;
+            }
             TransaktionsTyp::Spitzenverwertung { stück, preis } => {
                 write!(
                     w,
-                    "Spitzenverwertung\t{}\t{}\t\t\t\t",
+                    "Spitzenverwertung\t{}\t{}\t\t\t\t\t",
                     stück,
                     Eur(*preis, 4)
                 )?;
             }
 
-            TransaktionsTyp::Dividende { brutto, auszahlung } => {
+            TransaktionsTyp::Ausbuchung { stück, preis } => {
+                write!(w, "Ausbuchung\t{}\t{}\t\t\t\t\t", stück, Eur(*preis, 4))?;
+            }
+
+            TransaktionsTyp::Dividende {
+                brutto, auszahlung, ..
+            } => {
                 write!(
                     w,
-                    "Dividende\t\t\t{}\t{}\t\t",
+                    "Dividende\t\t\t\t{}\t{}\t\t",
                     Eur(*brutto, 2),
                     Eur(*auszahlung, 2)
                 )?;
             }
+            TransaktionsTyp::Staking { stück, preis } => {
+                write!(w, "Staking\t{}\t{}\t\t\t\t\t", stück, Eur(*preis, 4))?;
+            }
+            TransaktionsTyp::Stillhalterprämie { stück, preis } => {
+                write!(
+                    w,
+                    "Stillhalterprämie\t{}\t{}\t\t\t\t\t",
+                    stück,
+                    Eur(*preis, 4)
+                )?;
+            }
+            TransaktionsTyp::Glattstellung { stück, preis } => {
+                write!(w, "Glattstellung\t{}\t{}\t\t\t\t\t", stück, Eur(*preis, 4))?;
+            }
+            TransaktionsTyp::Verfall { stück } => {
+                write!(w, "Verfall\t{}\t\t\t\t\t\t", stück)?;
+            }
+            TransaktionsTyp::Ausübung {
+                stück, basispreis, ..
+            } => {
+                write!(w, "Ausübung\t{}\t{}\t\t\t\t\t", stück, Eur(*basispreis, 4))?;
+            }
+            TransaktionsTyp::Rückerstattung { betrag, .. } => {
+                write!(w, "Rückerstattung\t\t\t\t\t{}\t\t", Eur(*betrag, 2))?;
+            }
+            TransaktionsTyp::Kupon { betrag } => {
+                write!(
+                    w,
+                    "Kupon\t\t\t\t{}\t{}\t\t",
+                    Eur(*betrag, 2),
+                    Eur(*betrag, 2)
+                )?;
+            }
+            TransaktionsTyp::Einlagenrückzahlung { betrag, korrektur } => {
+                write!(
+                    w,
+                    "Einlagenrückzahlung\t\t{}\t\t\t{}\t\t",
+                    Eur(*korrektur, 4),
+                    Eur(*betrag, 2)
+                )?;
+            }
             TransaktionsTyp::Ausschüttung { brutto, melde_id } => {
                 let aktion = if melde_id.is_some() {
                     "Ausschüttung mit Meldung"
                 } else {
                     "Ausschüttung ohne Meldung"
                 };
-                write!(w, "{}\t\t\t{}\t\t", aktion, Eur(*brutto, 2))?;
+                write!(w, "{}\t\t\t\t{}\t\t", aktion, Eur(*brutto, 2))?;
                 if let Some(melde_id) = melde_id {
                     write!(w, "{melde_id}")?;
                 }
                 w.write_char('\t')?;
             }
+            TransaktionsTyp::Depotausgang { stück, .. } => {
+                write!(w, "Depotausgang\t{}\t\t\t\t\t\t", stück)?;
+            }
+            TransaktionsTyp::Depoteingang { stück, preis, .. } => {
+                write!(w, "Depoteingang\t{}\t{}\t\t\t\t\t", stück, Eur(*preis, 4))?;
+            }
+            TransaktionsTyp::Umtausch {
+                stück, zuzahlung, ..
+            } => {
+                write!(w, "Umtausch\t{}\t\t\t\t", stück)?;
+                if !zuzahlung.is_zero() {
+                    write!(w, "{}", Eur(*zuzahlung, 2))?;
+                }
+                w.write_str("\t\t")?;
+            }
+            TransaktionsTyp::Umtauscheingang { stück, preis, .. } => {
+                write!(
+                    w,
+                    "Umtauscheingang\t{}\t{}\t\t\t\t\t",
+                    stück,
+                    Eur(*preis, 4)
+                )?;
+            }
+            TransaktionsTyp::UnentgeltlicherErwerb { stück, preis, .. } => {
+                write!(
+                    w,
+                    "Unentgeltlicher Erwerb\t{}\t{}\t\t\t\t\t",
+                    stück,
+                    Eur(*preis, 4)
+                )?;
+            }
+            TransaktionsTyp::Schenkung { stück } => {
+                write!(w, "Schenkung\t{}\t\t\t\t\t\t", stück)?;
+            }
+            TransaktionsTyp::Bezugsrecht { anzahl } => {
+                write!(w, "Einbuchung Bezugsrechte\t{}\t\t\t\t\t\t", anzahl)?;
+            }
+            TransaktionsTyp::BezugsrechtVerkauf { anzahl, preis } => {
+                write!(
+                    w,
+                    "Verkauf Bezugsrechte\t{}\t{}\t\t\t\t\t",
+                    anzahl,
+                    Eur(*preis, 4)
+                )?;
+            }
+            TransaktionsTyp::BezugsrechtAusübung { stück, preis, .. } => {
+                write!(
+                    w,
+                    "Ausübung Bezugsrechte\t{}\t{}\t\t\t\t\t",
+                    stück,
+                    Eur(*preis, 4)
+                )?;
+            }
             TransaktionsTyp::Jahresmeldung { melde_id } => {
-                write!(w, "Jahresmeldung\t\t\t\t\t{}\t", melde_id)?;
+                write!(w, "Jahresmeldung\t\t\t\t\t\t{}\t", melde_id)?;
+            }
+            TransaktionsTyp::Schätzung {
+                letzter_kurs,
+                ausgangswert,
+                ..
+            } => {
+                write!(
+                    w,
+                    "Keine Meldung – pauschal geschätzt\t\t{}\t\t{}\t\t\t",
+                    Eur(*letzter_kurs, 4),
+                    Eur(*ausgangswert, 2)
+                )?;
+            }
+            TransaktionsTyp::SchätzungVerkauf { stück, preis } => {
+                write!(
+                    w,
+                    "Keine Meldung – pauschal geschätzt Verkauf\t{}\t{}\t\t\t\t\t",
+                    stück,
+                    Eur(*preis, 4)
+                )?;
             }
         }
 
+        if let Some(fremdwährung) = &transaktion.fremdwährung {
+            let kurs = Dezimal(fremdwährung.kurs, 4);
+            write!(w, "{}\t{}\t", fremdwährung.währung, kurs)?;
+        } else {
+            w.write_str("\t\t")?;
+        }
+
         schreibe_steuern(w, transaktion.steuer)?;
 
         w.write_char('\n')?;
@@ -113,6 +319,13 @@ pub fn schreibe_tsv<W: fmt::Write>(
     writeln!(w, "Bestand")
 }
 
+fn schreibe_spesen<W: fmt::Write>(w: &mut W, spesen: Zahl) -> fmt::Result {
+    if !spesen.is_zero() {
+        write!(w, "{}", Eur(spesen, 2))?;
+    }
+    w.write_char('\t')
+}
+
 fn schreibe_anfang<W: fmt::Write>(
     w: &mut W,
     wertpapier: &Wertpapier,
@@ -121,13 +334,17 @@ fn schreibe_anfang<W: fmt::Write>(
 ) -> fmt::Result {
     write!(
         w,
-        "{}\t{}\t{}\t{}\t{}\t{}\t",
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t",
         wertpapier.name,
         wertpapier.isin,
         wertpapier.typ,
+        wertpapier.depot.as_deref().unwrap_or_default(),
         datum,
         bestand.stück,
         Eur(bestand.preis, 4),
+        bestand.alt.stück,
+        Eur(bestand.alt.preis, 4),
+        bestand.bezugsrechte,
     )
 }
 
@@ -135,11 +352,21 @@ fn schreibe_steuern<W: fmt::Write>(w: &mut W, steuer: Steuer) -> fmt::Result {
     let mut verkauf = SteuerVerkauf::default();
     let mut dividende = SteuerDividende::default();
     let mut ausschüttung = SteuerAusschüttung::default();
+    let mut krypto = SteuerKrypto::default();
+    let mut derivat = SteuerDerivat::default();
     match steuer {
         Steuer::Keine => {}
         Steuer::Verkauf(steuer) => verkauf = steuer,
         Steuer::Dividende(steuer) => dividende = steuer,
         Steuer::Ausschüttung(steuer) => ausschüttung = steuer,
+        Steuer::Krypto(steuer) => {
+            verkauf.gewinn_altbestand = steuer.gewinn_altbestand;
+            krypto = steuer;
+        }
+        Steuer::Derivat(steuer) => {
+            verkauf.gewinn_altbestand = steuer.gewinn_altbestand;
+            derivat = steuer;
+        }
     }
 
     if !verkauf.überschüsse_994.is_zero() {
@@ -150,6 +377,10 @@ fn schreibe_steuern<W: fmt::Write>(w: &mut W, steuer: Steuer) -> fmt::Result {
         write!(w, "{}", Eur(verkauf.verluste_892, 2))?;
     }
     w.write_char('\t')?;
+    if !verkauf.gewinn_altbestand.is_zero() {
+        write!(w, "{}", Eur(verkauf.gewinn_altbestand, 2))?;
+    }
+    w.write_char('\t')?;
 
     if !dividende.dividendenerträge_863.is_zero() {
         write!(w, "{}", Eur(dividende.dividendenerträge_863, 2))?;
@@ -179,5 +410,27 @@ fn schreibe_steuern<W: fmt::Write>(w: &mut W, steuer: Steuer) -> fmt::Result {
     if !ausschüttung.anrechenbare_quellensteuer_998.is_zero() {
         write!(w, "{}", Eur(ausschüttung.anrechenbare_quellensteuer_998, 2))?;
     }
+    w.write_char('\t')?;
+
+    if !krypto.laufende_einkünfte_171.is_zero() {
+        write!(w, "{}", Eur(krypto.laufende_einkünfte_171, 2))?;
+    }
+    w.write_char('\t')?;
+    if !krypto.überschüsse_172.is_zero() {
+        write!(w, "{}", Eur(krypto.überschüsse_172, 2))?;
+    }
+    w.write_char('\t')?;
+    if !krypto.verluste_173.is_zero() {
+        write!(w, "{}", Eur(krypto.verluste_173, 2))?;
+    }
+    w.write_char('\t')?;
+
+    if !derivat.überschüsse_993.is_zero() {
+        write!(w, "{}", Eur(derivat.überschüsse_993, 2))?;
+    }
+    w.write_char('\t')?;
+    if !derivat.verluste_896.is_zero() {
+        write!(w, "{}", Eur(derivat.verluste_896, 2))?;
+    }
     w.write_char('\t')
 }
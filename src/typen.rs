@@ -1,4 +1,5 @@
-pub use crate::format::{Datum, Rational64 as Zahl, String, WertpapierTyp};
+pub use crate::dba::{Land, Quellensteuersatz};
+pub use crate::format::{Datum, Optionsart, Rational64 as Zahl, String, WertpapierTyp};
 
 #[derive(Debug)]
 pub struct Wertpapier {
@@ -6,11 +7,25 @@ pub struct Wertpapier {
     pub name: String,
     pub isin: String,
     pub symbol: Option<String>,
+    pub depot: Option<String>,
     pub jahre: Vec<Jahr>,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Bestand {
+    /// Alle Stück, inklusive dem Altbestand
+    pub stück: Zahl,
+    /// Der Durchschnittspreis des Neubestandes
+    pub preis: Zahl,
+    pub alt: Altbestand,
+    /// Eingebuchte Bezugsrechte, mit Anschaffungskosten von Null
+    pub bezugsrechte: Zahl,
+}
+
+/// Vor dem Stichtag angeschaffte Stück, welche nicht der Besteuerung von
+/// realisierten Wertsteigerungen unterliegen.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Altbestand {
     pub stück: Zahl,
     pub preis: Zahl,
 }
@@ -29,22 +44,217 @@ pub struct Transaktion {
     pub bestand: Bestand,
     pub typ: TransaktionsTyp,
     pub steuer: Steuer,
+    pub fremdwährung: Option<Fremdwährung>,
+}
+
+/// Die ursprüngliche Währung einer Transaktion, samt dem Referenzkurs
+/// (Einheiten der Währung pro EUR) zu dem die Beträge umgerechnet wurden.
+#[derive(Debug, Clone)]
+pub struct Fremdwährung {
+    pub währung: String,
+    pub kurs: Zahl,
+    /// Der ursprünglich angegebene Betrag in der Währung
+    pub betrag: Zahl,
+    /// Die ursprünglich angegebene Auszahlung einer Dividende
+    pub auszahlung: Option<Zahl>,
+}
+
+/// Ein Verkehrswert je Stück in EUR, wie er für Kapitalmaßnahmen angesetzt wird.
+#[derive(Debug, Clone)]
+pub struct Verkehrswert {
+    pub wert: Zahl,
+    pub quelle: Kursquelle,
+    /// Die Währung eines in Fremdwährung angegebenen Verkehrswertes
+    pub fremdwährung: Option<Fremdwährung>,
+}
+
+/// Woher ein angesetzter Wert stammt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kursquelle {
+    /// Ausdrücklich in den Daten angegeben, etwa laut Veröffentlichung des Emittenten
+    Angegeben,
+    /// Der Eröffnungskurs laut Kursabfrage
+    Abgefragt,
+    /// Die bei einer Ausgliederung abgespaltenen Anschaffungskosten
+    Ausgliederung,
 }
 
 #[derive(Debug)]
 
 pub enum TransaktionsTyp {
-    Kauf { stück: Zahl, preis: Zahl },
-    Verkauf { stück: Zahl, preis: Zahl },
+    Kauf {
+        stück: Zahl,
+        preis: Zahl,
+        spesen: Zahl,
+    },
+    Verkauf {
+        stück: Zahl,
+        preis: Zahl,
+        spesen: Zahl,
+    },
+
+    Split {
+        faktor: Zahl,
+    },
+    Gratisaktien {
+        stück: Zahl,
+    },
+    Fondsverschmelzung {
+        isin: String,
+        /// Das Symbol für die Rücknahmepreise des übernehmenden Fonds
+        symbol: Option<String>,
+        faktor: Zahl,
+    },
+    Aktiendividende {
+        stück: Zahl,
+        preis: Zahl,
+        quellensteuer: Zahl,
+        quellensteuersatz: Quellensteuersatz,
+    },
+    Ausgliederung {
+        faktor: Zahl,
+        isin: String,
+        eigener_kurs: Verkehrswert,
+        anderer_kurs: Verkehrswert,
+    },
+    Einbuchung {
+        stück: Zahl,
+        preis: Zahl,
+        quelle: Kursquelle,
+    },
+    Spitzenverwertung {
+        stück: Zahl,
+        preis: Zahl,
+    },
+    Ausbuchung {
+        stück: Zahl,
+        preis: Zahl,
+    },
+
+    /// Kauf einer Anleihe, mit dem Kurs in Prozent des Nominalbetrages
+    AnleiheKauf {
+        nominale: Zahl,
+        kurs: Zahl,
+        stückzinsen: Zahl,
+        spesen: Zahl,
+    },
+    /// Verkauf einer Anleihe, mit dem Kurs in Prozent des Nominalbetrages
+    AnleiheVerkauf {
+        nominale: Zahl,
+        kurs: Zahl,
+        stückzinsen: Zahl,
+        spesen: Zahl,
+    },
+    Tilgung {
+        nominale: Zahl,
+        kurs: Zahl,
+    },
+
+    Dividende {
+        brutto: Zahl,
+        auszahlung: Zahl,
+        quellensteuersatz: Quellensteuersatz,
+    },
+    Rückerstattung {
+        betrag: Zahl,
+        /// Das Land und Jahr der Dividenden, deren Quellensteuer rückerstattet wurde
+        land: Land,
+        jahr: i32,
+    },
+    Kupon {
+        betrag: Zahl,
+    },
+    Staking {
+        stück: Zahl,
+        preis: Zahl,
+    },
+
+    /// Verkauf einer Option als Stillhalter, mit der Prämie je Stück
+    Stillhalterprämie {
+        stück: Zahl,
+        preis: Zahl,
+    },
+    Glattstellung {
+        stück: Zahl,
+        preis: Zahl,
+    },
+    Verfall {
+        stück: Zahl,
+    },
+    Ausübung {
+        stück: Zahl,
+        art: Optionsart,
+        basiswert: String,
+        basispreis: Zahl,
+    },
+    Einlagenrückzahlung {
+        betrag: Zahl,
+        /// Die Minderung der Anschaffungskosten je Stück
+        korrektur: Zahl,
+    },
+    Ausschüttung {
+        brutto: Zahl,
+        melde_id: Option<u32>,
+    },
+    Jahresmeldung {
+        melde_id: u32,
+    },
+    /// Die pauschale Schätzung der ausschüttungsgleichen Erträge zum Jahresende, mit dem
+    /// ersten und letzten Rücknahmepreis des Jahres
+    Schätzung {
+        erster_kurs: Option<Zahl>,
+        letzter_kurs: Zahl,
+        ausgangswert: Zahl,
+    },
+    /// Die pauschale Schätzung für während des Jahres veräußerte Anteile
+    SchätzungVerkauf {
+        stück: Zahl,
+        preis: Zahl,
+    },
 
-    Split { faktor: Zahl },
-    Ausgliederung { faktor: Zahl, isin: String },
-    Einbuchung { stück: Zahl, preis: Zahl },
-    Spitzenverwertung { stück: Zahl, preis: Zahl },
+    Depotausgang {
+        stück: Zahl,
+        depot: String,
+    },
+    Depoteingang {
+        stück: Zahl,
+        preis: Zahl,
+        depot: String,
+    },
 
-    Dividende { brutto: Zahl, auszahlung: Zahl },
-    Ausschüttung { brutto: Zahl, melde_id: Option<u32> },
-    Jahresmeldung { melde_id: u32 },
+    Umtausch {
+        stück: Zahl,
+        isin: String,
+        neue_stück: Zahl,
+        zuzahlung: Zahl,
+    },
+    Umtauscheingang {
+        stück: Zahl,
+        preis: Zahl,
+        isin: String,
+    },
+
+    UnentgeltlicherErwerb {
+        stück: Zahl,
+        preis: Zahl,
+        anschaffung: Datum,
+    },
+    Schenkung {
+        stück: Zahl,
+    },
+
+    Bezugsrecht {
+        anzahl: Zahl,
+    },
+    BezugsrechtVerkauf {
+        anzahl: Zahl,
+        preis: Zahl,
+    },
+    BezugsrechtAusübung {
+        anzahl: Zahl,
+        stück: Zahl,
+        preis: Zahl,
+    },
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -53,12 +263,16 @@ pub enum Steuer {
     Verkauf(SteuerVerkauf),
     Dividende(SteuerDividende),
     Ausschüttung(SteuerAusschüttung),
+    Krypto(SteuerKrypto),
+    Derivat(SteuerDerivat),
 }
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct SteuerVerkauf {
     pub überschüsse_994: Zahl,
     pub verluste_892: Zahl,
+    /// Gewinn oder Verlust aus dem Altbestand, nicht steuerpflichtig
+    pub gewinn_altbestand: Zahl,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -66,6 +280,8 @@ pub struct SteuerDividende {
     pub dividendenerträge_863: Zahl,
     pub gezahlte_inländische_kest_899: Zahl,
     pub anrechenbare_quellensteuer_998: Zahl,
+    /// Den anrechenbaren Satz übersteigende Quellensteuer, nur im Quellenstaat rückforderbar
+    pub nicht_anrechenbare_quellensteuer: Zahl,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -75,18 +291,56 @@ pub struct SteuerAusschüttung {
     pub anrechenbare_quellensteuer_998: Zahl,
 }
 
+/// Einkünfte aus Kryptowährungen laut § 27b, welche getrennt erklärt werden.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SteuerKrypto {
+    pub laufende_einkünfte_171: Zahl,
+    pub überschüsse_172: Zahl,
+    pub verluste_173: Zahl,
+    /// Gewinn oder Verlust aus dem Altvermögen, nicht steuerpflichtig
+    pub gewinn_altbestand: Zahl,
+}
+
+/// Einkünfte aus Derivaten laut § 27 (4), welche getrennt erklärt werden.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SteuerDerivat {
+    pub überschüsse_993: Zahl,
+    pub verluste_896: Zahl,
+    /// Gewinn oder Verlust aus dem Altbestand, nicht steuerpflichtig
+    pub gewinn_altbestand: Zahl,
+}
+
+/// Die Kennzahlen der E1kv, jeweils für Einkünfte aus inländischen Depots (mit KeSt-Abzug) und
+/// ausländischen Depots.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct SteuerJahr {
     pub jahr: i32,
 
+    pub überschüsse_981: Zahl,
     pub überschüsse_994: Zahl,
+    pub verluste_891: Zahl,
     pub verluste_892: Zahl,
 
+    pub dividendenerträge_862: Zahl,
     pub dividendenerträge_863: Zahl,
 
+    pub ausschüttungen_897: Zahl,
     pub ausschüttungen_898: Zahl,
+    pub ausschüttungsgleiche_erträge_936: Zahl,
     pub ausschüttungsgleiche_erträge_937: Zahl,
 
+    pub krypto_laufende_einkünfte_174: Zahl,
+    pub krypto_laufende_einkünfte_171: Zahl,
+    pub krypto_überschüsse_175: Zahl,
+    pub krypto_überschüsse_172: Zahl,
+    pub krypto_verluste_176: Zahl,
+    pub krypto_verluste_173: Zahl,
+
+    pub derivate_überschüsse_982: Zahl,
+    pub derivate_überschüsse_993: Zahl,
+    pub derivate_verluste_893: Zahl,
+    pub derivate_verluste_896: Zahl,
+
     pub gezahlte_inländische_kest_899: Zahl,
     pub anrechenbare_quellensteuer_998: Zahl,
 }
@@ -106,11 +360,83 @@ pub fn zahl_aus_float(f: f64) -> Zahl {
 }
 
 impl Bestand {
+    pub fn summe(&self) -> Zahl {
+        self.neu_stück() * self.preis + self.alt.summe()
+    }
+
+    pub fn neu_stück(&self) -> Zahl {
+        self.stück - self.alt.stück
+    }
+}
+
+impl Altbestand {
     pub fn summe(&self) -> Zahl {
         self.stück * self.preis
     }
 }
 
+impl WertpapierTyp {
+    /// Ab diesem Tag angeschaffte Stück gehören zum Neubestand.
+    pub fn stichtag_neubestand(&self) -> Datum {
+        match self {
+            // Laut § 124b Z 185 lit. a gilt für andere Wirtschaftsgüter, wie etwa Anleihen,
+            // und Derivate erst der Erwerb ab dem 1. April 2012 als Neubestand
+            WertpapierTyp::Anleihe | WertpapierTyp::Derivat => {
+                Datum::from_ymd_opt(2012, 4, 1).unwrap()
+            }
+            // Laut § 124b Z 384 gelten nur nach dem 28. Februar 2021 angeschaffte
+            // Kryptowährungen als Neuvermögen
+            WertpapierTyp::Krypto => Datum::from_ymd_opt(2021, 3, 1).unwrap(),
+            _ => Datum::from_ymd_opt(2011, 1, 1).unwrap(),
+        }
+    }
+}
+
+impl TransaktionsTyp {
+    /// Der tatsächliche Geldfluss inklusive Spesen, negativ bei Auszahlungen vom Konto.
+    pub fn geldfluss(&self) -> Zahl {
+        match self {
+            TransaktionsTyp::Kauf {
+                stück,
+                preis,
+                spesen,
+            } => -(stück * preis + spesen),
+            TransaktionsTyp::Verkauf {
+                stück,
+                preis,
+                spesen,
+            } => stück * preis - spesen,
+            TransaktionsTyp::Spitzenverwertung { stück, preis }
+            | TransaktionsTyp::Ausbuchung { stück, preis } => stück * preis,
+            TransaktionsTyp::AnleiheKauf {
+                nominale,
+                kurs,
+                stückzinsen,
+                spesen,
+            } => -(nominale * kurs / 100 + stückzinsen + spesen),
+            TransaktionsTyp::AnleiheVerkauf {
+                nominale,
+                kurs,
+                stückzinsen,
+                spesen,
+            } => nominale * kurs / 100 + stückzinsen - spesen,
+            TransaktionsTyp::Tilgung { nominale, kurs } => nominale * kurs / 100,
+            TransaktionsTyp::Kupon { betrag } => *betrag,
+            TransaktionsTyp::Stillhalterprämie { stück, preis } => stück * preis,
+            TransaktionsTyp::Glattstellung { stück, preis } => -(stück * preis),
+            TransaktionsTyp::Umtausch { zuzahlung, .. } => *zuzahlung,
+            TransaktionsTyp::BezugsrechtVerkauf { anzahl, preis } => anzahl * preis,
+            TransaktionsTyp::BezugsrechtAusübung { stück, preis, .. } => -(stück * preis),
+            TransaktionsTyp::Dividende { auszahlung, .. } => *auszahlung,
+            TransaktionsTyp::Einlagenrückzahlung { betrag, .. } => *betrag,
+            TransaktionsTyp::Aktiendividende { quellensteuer, .. } => -quellensteuer,
+            TransaktionsTyp::Ausschüttung { brutto, .. } => *brutto,
+            TransaktionsTyp::Rückerstattung { betrag, .. } => *betrag,
+            _ => Zahl::default(),
+        }
+    }
+}
+
 impl Wertpapier {
     pub fn iter_jahre(&self, jahr: Option<i32>) -> impl Iterator<Item = &Jahr> {
         self.jahre
@@ -90,9 +90,12 @@ impl WährungenInner {
             .get(&kurs.währung)
             .expect("Kurse sollten verfügbar sein");
 
+        // An Wochenenden und Feiertagen gibt es keinen Referenzkurs,
+        // dann gilt der letzte davor veröffentlichte.
         let umrechnungskurs = kurse
-            .get(&kurs.datum)
-            .copied()
+            .range(..=kurs.datum)
+            .next_back()
+            .map(|(_datum, kurs)| *kurs)
             .context("Es sollte einen Umrechnungskurs geben")?;
 
         Ok(kurs.wert / umrechnungskurs)
@@ -0,0 +1,237 @@
+use std::fmt;
+
+use anyhow::Result;
+use num_traits::Zero;
+
+use crate::steuern::runde;
+use crate::{Datum, SteuerJahr, Zahl};
+
+/// Die Einkünfte eines Jahres im Aufbau der E1kv, laut dem Schema in `schema/e1kv.xsd`. Dieses
+/// ist nicht das offizielle Schema des BMF, sondern nach dem Aufbau der Erklärungsübermittlung
+/// nachgebildet. Die Ausgabe ist nicht gegen FinanzOnline geprüft und nicht zum Hochladen gedacht.
+/// Vor dem Schreiben sind die Kennzahlen mit [`kennzahlen_prüfen`] zu prüfen.
+pub struct XmlErklärung<'a> {
+    pub summe: &'a SteuerJahr,
+    pub steuernummer: &'a str,
+    pub erstellt: Datum,
+}
+
+/// Prüft die Steuernummer, welche aus der zweistelligen Finanzamtsnummer und der siebenstelligen
+/// Steuernummer besteht, etwa `12-345/6789`.
+pub fn steuernummer_prüfen(steuernummer: &str) -> Result<String> {
+    let ziffern: String = steuernummer
+        .chars()
+        .filter(|c| !matches!(c, '-' | '/' | ' '))
+        .collect();
+    anyhow::ensure!(
+        ziffern.len() == 9 && ziffern.chars().all(|c| c.is_ascii_digit()),
+        "`{steuernummer}` ist keine gültige Steuernummer, erwartet werden 9 Ziffern"
+    );
+    Ok(ziffern)
+}
+
+/// Die Kennzahlen in der Reihenfolge des Formulars.
+pub fn kennzahlen(summe: &SteuerJahr) -> [(u16, Zahl); 22] {
+    [
+        (862, summe.dividendenerträge_862),
+        (863, summe.dividendenerträge_863),
+        (897, summe.ausschüttungen_897),
+        (898, summe.ausschüttungen_898),
+        (936, summe.ausschüttungsgleiche_erträge_936),
+        (937, summe.ausschüttungsgleiche_erträge_937),
+        (981, summe.überschüsse_981),
+        (994, summe.überschüsse_994),
+        (891, summe.verluste_891),
+        (892, summe.verluste_892),
+        (982, summe.derivate_überschüsse_982),
+        (993, summe.derivate_überschüsse_993),
+        (893, summe.derivate_verluste_893),
+        (896, summe.derivate_verluste_896),
+        (174, summe.krypto_laufende_einkünfte_174),
+        (171, summe.krypto_laufende_einkünfte_171),
+        (175, summe.krypto_überschüsse_175),
+        (172, summe.krypto_überschüsse_172),
+        (176, summe.krypto_verluste_176),
+        (173, summe.krypto_verluste_173),
+        (899, summe.gezahlte_inländische_kest_899),
+        (998, summe.anrechenbare_quellensteuer_998),
+    ]
+}
+
+/// Laut Schema sind nur positive Beträge zulässig, Verluste werden unter eigenen Kennzahlen erklärt.
+pub fn kennzahlen_prüfen(summe: &SteuerJahr) -> Result<()> {
+    for (kennzahl, betrag) in kennzahlen(summe) {
+        anyhow::ensure!(
+            runde(betrag, 2) >= Zahl::zero(),
+            "Negativer Betrag {betrag} für Kennzahl {kennzahl} kann nicht übermittelt werden"
+        );
+    }
+    Ok(())
+}
+
+/// Ein nicht negativer Betrag mit zwei Nachkommastellen und Punkt als Dezimaltrennzeichen.
+struct XmlBetrag(Zahl);
+impl fmt::Display for XmlBetrag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cent = (runde(self.0, 2) * 100).to_integer();
+        debug_assert!(cent >= 0, "negative Beträge sind im Schema nicht zulässig");
+        write!(f, "{}.{:02}", cent / 100, cent % 100)
+    }
+}
+
+impl fmt::Display for XmlErklärung<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let jahr = self.summe.jahr;
+
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(f, "<ERKLAERUNGS_UEBERMITTLUNG>")?;
+        writeln!(f, "  <INFO_DATEN>")?;
+        writeln!(
+            f,
+            "    <ART_IDENTIFIKATIONSBEGRIFF>FASTNR</ART_IDENTIFIKATIONSBEGRIFF>"
+        )?;
+        writeln!(
+            f,
+            "    <IDENTIFIKATIONSBEGRIFF>{}</IDENTIFIKATIONSBEGRIFF>",
+            self.steuernummer
+        )?;
+        writeln!(f, "    <PAKET_NR>1</PAKET_NR>")?;
+        writeln!(
+            f,
+            r#"    <DATUM_ERSTELLUNG type="datum">{}</DATUM_ERSTELLUNG>"#,
+            self.erstellt
+        )?;
+        writeln!(f, "    <ANZAHL_ERKLAERUNGEN>1</ANZAHL_ERKLAERUNGEN>")?;
+        writeln!(f, "  </INFO_DATEN>")?;
+
+        writeln!(f, r#"  <ERKLAERUNG art="E1kv">"#)?;
+        writeln!(f, "    <SATZNR>1</SATZNR>")?;
+        writeln!(f, "    <ALLGEMEINE_DATEN>")?;
+        writeln!(f, "      <ANBRINGEN>E1kv</ANBRINGEN>")?;
+        writeln!(f, r#"      <ZRVON type="jahr">{jahr}</ZRVON>"#)?;
+        writeln!(f, r#"      <ZRBIS type="jahr">{jahr}</ZRBIS>"#)?;
+        writeln!(f, "      <FASTNR>{}</FASTNR>", self.steuernummer)?;
+        writeln!(f, "    </ALLGEMEINE_DATEN>")?;
+
+        // Wie im Formular werden nur die ausgefüllten Kennzahlen übermittelt.
+        for (kennzahl, betrag) in kennzahlen(self.summe) {
+            if !betrag.is_zero() {
+                writeln!(
+                    f,
+                    r#"    <KZ{kennzahl} type="kz">{}</KZ{kennzahl}>"#,
+                    XmlBetrag(betrag)
+                )?;
+            }
+        }
+
+        writeln!(f, "  </ERKLAERUNG>")?;
+        writeln!(f, "</ERKLAERUNGS_UEBERMITTLUNG>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    const SCHEMA: &str = include_str!("../schema/e1kv.xsd");
+
+    /// Alle im Schema deklarierten Elemente, in der Reihenfolge ihrer Deklaration.
+    fn schema_elemente() -> Vec<&'static str> {
+        SCHEMA
+            .split(r#"<xs:element name=""#)
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .collect()
+    }
+
+    /// Eine einfache Prüfung gegen das nachgebildete Schema, ohne XSD-Validator: Alle Elemente
+    /// müssen deklariert sein, und die Kennzahlen in der Reihenfolge des Schemas vorkommen. Ob
+    /// FinanzOnline die Datei annimmt, zeigt dies nicht.
+    fn schema_prüfen(xml: &str) {
+        let deklariert = schema_elemente();
+        let mut vorkommen = vec![];
+        for tag in xml.split('<').skip(1) {
+            if tag.starts_with('?') || tag.starts_with('/') {
+                continue;
+            }
+            let name = tag
+                .split(|c: char| c == '>' || c.is_whitespace())
+                .next()
+                .unwrap();
+            let position = deklariert
+                .iter()
+                .position(|d| *d == name)
+                .unwrap_or_else(|| panic!("`{name}` ist im Schema nicht deklariert"));
+            vorkommen.push((name, position));
+        }
+        let kennzahlen: Vec<_> = vorkommen
+            .iter()
+            .filter(|(name, _)| name.starts_with("KZ"))
+            .collect();
+        assert!(
+            kennzahlen.windows(2).all(|w| w[0].1 < w[1].1),
+            "Kennzahlen nicht in der Reihenfolge des Schemas: {kennzahlen:?}"
+        );
+        let pflicht: BTreeSet<_> =
+            ["INFO_DATEN", "ERKLAERUNG", "ALLGEMEINE_DATEN", "FASTNR"].into();
+        let vorhanden: BTreeSet<_> = vorkommen.iter().map(|(name, _)| *name).collect();
+        assert!(pflicht.is_subset(&vorhanden));
+    }
+
+    #[test]
+    fn schema_deckt_alle_kennzahlen_ab() {
+        let deklariert = schema_elemente();
+        for (kennzahl, _) in kennzahlen(&SteuerJahr::default()) {
+            let name = format!("KZ{kennzahl}");
+            assert!(
+                deklariert.contains(&name.as_str()),
+                "`{name}` fehlt im Schema"
+            );
+        }
+    }
+
+    #[test]
+    fn erklärung_wie_fixture() {
+        let summe = SteuerJahr {
+            überschüsse_981: Zahl::new(12050, 100),
+            überschüsse_994: 1500.into(),
+            verluste_892: Zahl::new(30025, 100),
+            dividendenerträge_863: Zahl::new(84733, 100),
+            ausschüttungsgleiche_erträge_937: Zahl::new(1234567, 10000),
+            derivate_verluste_896: 80.into(),
+            krypto_überschüsse_172: 250.into(),
+            gezahlte_inländische_kest_899: Zahl::new(3314, 100),
+            anrechenbare_quellensteuer_998: Zahl::new(12710, 100),
+            ..SteuerJahr::new(2023)
+        };
+        let steuernummer = steuernummer_prüfen("12-345/6789").unwrap();
+        let erklärung = XmlErklärung {
+            summe: &summe,
+            steuernummer: &steuernummer,
+            erstellt: Datum::from_ymd_opt(2024, 3, 15).unwrap(),
+        };
+        kennzahlen_prüfen(&summe).unwrap();
+        let xml = erklärung.to_string();
+
+        schema_prüfen(&xml);
+        assert_eq!(xml, include_str!("../fixtures/e1kv-2023.xml"));
+    }
+
+    #[test]
+    fn negative_beträge_abgelehnt() {
+        let summe = SteuerJahr {
+            ausschüttungsgleiche_erträge_937: (-10).into(),
+            ..SteuerJahr::new(2023)
+        };
+        assert!(kennzahlen_prüfen(&summe).is_err());
+        assert!(kennzahlen_prüfen(&SteuerJahr::new(2023)).is_ok());
+    }
+
+    #[test]
+    fn steuernummer_ungültig() {
+        assert!(steuernummer_prüfen("12-345/678").is_err());
+        assert!(steuernummer_prüfen("12-345/678x").is_err());
+    }
+}